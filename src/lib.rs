@@ -3,4 +3,4 @@ mod loader;
 
 pub use parser::*;
 pub use parse_error::*;
-pub use loader::World;
\ No newline at end of file
+pub use loader::{BlockInfo, BlockVisitor, FsRegionSource, RegionSource, World};
\ No newline at end of file
@@ -1,41 +1,210 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::OsString;
-use std::fs;
-use std::fs::DirEntry;
+use std::fmt::Debug;
+use std::{fs, io};
+use std::fs::{DirEntry, File};
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 use log::{debug, error};
-use crate::{Block, McaParseError, Position};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use crate::{Block, BoundingBox, McaParseError, Position, Result};
 use crate::parser::chunk::Chunk;
 use crate::parser::level::Level;
-use crate::parser::region::Region;
+use crate::parser::region::{ParseOptions, Region};
+
+/// A block together with what's stored about its position, see [`World::get_block_info`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockInfo {
+    pub block: Block,
+    /// The biome, `None` for chunks from before 1.18 which store biomes per chunk
+    pub biome: Option<String>,
+    /// Block and sky light (0-15), 0 where the chunk has no light stored
+    pub block_light: u8,
+    pub sky_light: u8,
+}
+
+/// Receives blocks from [`World::visit_blocks`].
+pub trait BlockVisitor {
+    fn visit(&mut self, pos: Position, block: &Block);
+}
+
+/// Where the region files of a world are read from, e.g. the region directory or an archive.
+///
+/// Sources have to be `Send` and `Sync`, so regions can be read in parallel.
+pub trait RegionSource: Debug + Send + Sync {
+    /// Reads the bytes of a region file. Missing regions are expected to be a [`McaParseError::WorldLoadError`],
+    /// which is treated as ungenerated terrain.
+    fn read_region(&self, pos: Position) -> Result<Vec<u8>>;
+
+    /// The positions of all regions in the source.
+    fn region_positions(&self) -> Vec<Position>;
+
+    /// Reads a single chunk by chunk position. Defaults to reading the whole region, sources that can seek should
+    /// only read the chunk itself.
+    fn read_chunk(&self, chunk_pos: Position, options: &ParseOptions) -> Result<Option<Chunk>> {
+        let region_data = self.read_region(Position::new(chunk_pos.x>>5, 0, chunk_pos.z>>5))?;
+        Region::read_chunk_with_options(&mut Cursor::new(region_data), chunk_pos.x.rem_euclid(32), chunk_pos.z.rem_euclid(32), options)
+    }
+
+    /// Replaces the bytes of a region file, used by [`World::save`]. Defaults to failing, for read-only sources.
+    fn write_region(&self, pos: Position, _data: &[u8]) -> Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, format!("Region source can't write r.{}.{}.mca", pos.x, pos.z)).into())
+    }
+}
+
+/// Reads `r.<x>.<z>.mca` files from a region directory, the default source of a [`World`].
+#[derive(Debug, Clone)]
+pub struct FsRegionSource {
+    region_path: PathBuf,
+}
+
+impl FsRegionSource {
+    pub fn new<P: AsRef<Path>>(region_path: P) -> Self {
+        Self {
+            region_path: region_path.as_ref().to_path_buf(),
+        }
+    }
+
+    fn region_file(&self, pos: Position) -> PathBuf {
+        self.region_path.join(format!("r.{}.{}.mca", pos.x, pos.z))
+    }
+
+    /// Parses a region file name like `r.-1.2.mca` into its region position.
+    fn parse_region_filename(name: &str) -> Option<Position> {
+        let mut coords = name.strip_prefix("r.")?.strip_suffix(".mca")?.split('.');
+        let x = coords.next()?.parse().ok()?;
+        let z = coords.next()?.parse().ok()?;
+        if coords.next().is_some() {
+            return None;
+        }
+        Some(Position::new(x, 0, z))
+    }
+}
+
+impl RegionSource for FsRegionSource {
+    fn read_region(&self, pos: Position) -> Result<Vec<u8>> {
+        debug!("Loading region: r.{}.{}.mca", pos.x, pos.z);
+        Ok(fs::read(self.region_file(pos))?)
+    }
+
+    fn region_positions(&self) -> Vec<Position> {
+        let entries = match fs::read_dir(&self.region_path) {
+            Ok(entries) => entries,
+            // Worlds without an overworld
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return vec![],
+            Err(e) => {
+                error!("Error reading region directory: {}", e);
+                return vec![];
+            }
+        };
+        entries.filter_map(|e| e.ok())
+            .filter_map(|e| Self::parse_region_filename(&e.file_name().to_string_lossy()))
+            .collect()
+    }
+
+    fn read_chunk(&self, chunk_pos: Position, options: &ParseOptions) -> Result<Option<Chunk>> {
+        let region_pos = Position::new(chunk_pos.x>>5, 0, chunk_pos.z>>5);
+        debug!("Loading chunk {} from region: r.{}.{}.mca", chunk_pos, region_pos.x, region_pos.z);
+        let mut file = File::open(self.region_file(region_pos))?;
+        Region::read_chunk_with_options(&mut file, chunk_pos.x.rem_euclid(32), chunk_pos.z.rem_euclid(32), options)
+    }
+
+    fn write_region(&self, pos: Position, data: &[u8]) -> Result<()> {
+        debug!("Saving region: r.{}.{}.mca", pos.x, pos.z);
+        // Written next to the region first, so a failed write doesn't leave a truncated region behind
+        let region_file = self.region_file(pos);
+        let temp_file = region_file.with_extension("mca.tmp");
+        fs::write(&temp_file, data)?;
+        fs::rename(temp_file, region_file)?;
+        Ok(())
+    }
+}
 
 #[derive(Debug)]
 pub struct World {
+    world_path: PathBuf,
     level_dir_entries: Vec<DirEntry>,
     level: Level,
 
-    region_path: PathBuf,
-    loaded_regions: BTreeMap<Position, Region>
+    regions: Box<dyn RegionSource>,
+    loaded_regions: BTreeMap<Position, Region>,
+    /// Regions that are missing or failed to parse, so lookups around them don't read them again every time
+    unavailable_regions: BTreeSet<Position>,
+
+    /// When set, only the requested chunks are read from the region files instead of whole regions
+    lazy_chunks: bool,
+    /// Chunks read in lazy mode by chunk position, `None` if the chunk doesn't exist or failed to parse
+    loaded_chunks: BTreeMap<Position, Option<Chunk>>,
+
+    parse_options: ParseOptions,
+
+    /// Chunk positions of the chunks changed by edits since they were loaded
+    dirty_chunks: BTreeSet<Position>,
 }
 
 impl World {
     /// Loads a Minecraft world from its path.
-    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, McaParseError> {
-        let world_dir = fs::read_dir(path)?.filter_map(|e| e.ok()).collect::<Vec<DirEntry>>();
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let world_path = path.as_ref().to_path_buf();
+        let world_dir = fs::read_dir(&world_path)?.filter_map(|e| e.ok()).collect::<Vec<DirEntry>>();
         let level_dat = world_dir.iter().find(|e| e.file_name() == OsString::from("level.dat")).ok_or(McaParseError::InvalidWorld)?;
         let level_data = fs::read(level_dat.path())?;
         let level = Level::parse_level(level_data)?;
 
-        let region_path = world_dir.iter().find(|e| e.file_name() == OsString::from("region")).ok_or(McaParseError::InvalidWorld)?.path();
+        // The overworld can be missing, e.g. on servers that deleted it, as long as there's another dimension
+        let region_path = world_path.join("region");
+        if !region_path.is_dir() && !Self::has_other_dimensions(&world_dir, &world_path) {
+            return Err(McaParseError::InvalidWorld);
+        }
         Ok(Self {
+            world_path,
             level_dir_entries: world_dir,
             level,
-            region_path,
+            regions: Box::new(FsRegionSource::new(region_path)),
             loaded_regions: BTreeMap::new(),
+            unavailable_regions: BTreeSet::new(),
+            lazy_chunks: false,
+            loaded_chunks: BTreeMap::new(),
+            parse_options: ParseOptions::default(),
+            dirty_chunks: BTreeSet::new(),
         })
     }
 
+    /// The parsed `level.dat` of the world.
+    pub fn level(&self) -> &Level {
+        &self.level
+    }
+
+    /// The chunk containing the world spawn point
+    pub fn spawn_chunk(&mut self) -> Option<Chunk> {
+        let spawn = self.level.spawn()?;
+        self.get_chunk(spawn)
+    }
+
+    /// The position of the region containing the world spawn point
+    pub fn spawn_region(&mut self) -> Option<Position> {
+        Some(self.level.spawn()?.region_in_world())
+    }
+
+    /// Switches between loading whole regions (the default) and only reading the chunks that are accessed.
+    ///
+    /// Lazy loading is a lot faster for a few scattered lookups, whole regions are faster when most of a region is
+    /// going to be accessed anyways.
+    pub fn set_lazy_loading(&mut self, lazy: bool) {
+        self.lazy_chunks = lazy;
+    }
+
+    /// Sets the options used for parsing regions and chunks loaded from now on, already loaded ones are kept as is.
+    pub fn set_parse_options(&mut self, options: ParseOptions) {
+        self.parse_options = options;
+    }
+
     pub fn get_block(&mut self, pos: Position) -> Option<Block> {
+        if self.lazy_chunks {
+            return self.lazy_chunk(pos.chunk_in_region())?.get(pos);
+        }
         // I would like to extract the region getting to its own function, but lifetime shenanigans causes trouble
         let region = if let Some(region) = self.loaded_regions.get(&pos.region_in_world()) {
             region
@@ -46,25 +215,460 @@ impl World {
         region.get(pos)
     }
 
+    /// Block light (0-15) at the position, `None` if the chunk isn't generated
+    pub fn get_block_light(&mut self, pos: Position) -> Option<u8> {
+        self.with_loaded_chunk(pos, |chunk| chunk.get_block_light(pos))
+    }
+
+    /// Sky light (0-15) at the position, `None` if the chunk isn't generated
+    pub fn get_sky_light(&mut self, pos: Position) -> Option<u8> {
+        self.with_loaded_chunk(pos, |chunk| chunk.get_sky_light(pos))
+    }
+
+    /// Gets the block, biome and light at the position at once, looking the section up only once. `None` if the
+    /// chunk isn't generated or the position is outside of its height.
+    pub fn get_block_info(&mut self, pos: Position) -> Option<BlockInfo> {
+        self.with_loaded_chunk(pos, |chunk| {
+            match chunk.sections().iter().find(|section| section.y() as i32 == pos.y>>4) {
+                Some(section) => Some(BlockInfo {
+                    block: section.get(pos),
+                    biome: section.biome(pos).map(|biome| biome.to_string()),
+                    block_light: section.block_light(pos).unwrap_or(0),
+                    sky_light: section.sky_light(pos).unwrap_or(0),
+                }),
+                // Sections that aren't stored are air, as long as they're inside of the chunk
+                None => chunk.get_ref(pos).map(|block| BlockInfo {
+                    block: block.clone(),
+                    biome: None,
+                    block_light: 0,
+                    sky_light: 0,
+                }),
+            }
+        })
+    }
+
+    /// The light level the game uses for e.g. mob spawning: the highest of block light and sky light, with the sky
+    /// light lowered by `sky_darkening` (0 at day, up to 11 at night).
+    pub fn get_light(&mut self, pos: Position, sky_darkening: u8) -> Option<u8> {
+        self.with_loaded_chunk(pos, |chunk| {
+            Some(chunk.get_block_light(pos)?.max(chunk.get_sky_light(pos)?.saturating_sub(sky_darkening)))
+        })
+    }
+
+    /// Finds the block with the identifier closest to `origin`, searching at most `max_radius` blocks away on
+    /// each axis.
+    ///
+    /// The search goes outwards in cube shaped shells, and stops once no block in the remaining shells can be
+    /// closer than the best match so far.
+    pub fn nearest_block(&mut self, origin: Position, identifier: &str, max_radius: i32) -> Option<(Position, Block)> {
+        let mut nearest: Option<(f64, Position, Block)> = None;
+        for radius in 0..=max_radius.max(0) {
+            if nearest.as_ref().map_or(false, |(distance, _, _)| radius as f64 > *distance) {
+                break;
+            }
+            // Each chunk the shell goes through is only looked up once
+            let mut shell_by_chunk: BTreeMap<Position, Vec<Position>> = BTreeMap::new();
+            for pos in Self::shell(origin, radius) {
+                shell_by_chunk.entry(pos.chunk_in_region()).or_default().push(pos);
+            }
+            for positions in shell_by_chunk.values() {
+                let found = self.with_loaded_chunk(positions[0], |chunk| {
+                    positions.iter()
+                        .filter_map(|pos| Some((*pos, chunk.try_get(*pos).ok().flatten()?)))
+                        .filter(|(_, block)| block.is(identifier))
+                        .map(|(pos, block)| (origin.distance(&pos), pos, block.clone()))
+                        .min_by(|a, b| a.0.total_cmp(&b.0))
+                });
+                if let Some((distance, pos, block)) = found {
+                    if nearest.as_ref().map_or(true, |(nearest, _, _)| distance < *nearest) {
+                        nearest = Some((distance, pos, block));
+                    }
+                }
+            }
+        }
+        nearest.map(|(_, pos, block)| (pos, block))
+    }
+
+    /// The positions exactly `radius` blocks away from `center` on at least one axis
+    fn shell(center: Position, radius: i32) -> impl Iterator<Item = Position> {
+        (-radius..=radius).flat_map(move |dx| (-radius..=radius).flat_map(move |dy| {
+            // Inside of the shell only the two faces on z are part of it
+            let dz: Vec<i32> = if dx.abs() == radius || dy.abs() == radius {
+                (-radius..=radius).collect()
+            } else {
+                vec![-radius, radius]
+            };
+            dz.into_iter().map(move |dz| Position::new(center.x + dx, center.y + dy, center.z + dz))
+        }))
+    }
+
+    /// Runs `f` on the chunk containing the block position, loading it like [`World::get_block`] does.
+    fn with_loaded_chunk<T>(&mut self, pos: Position, f: impl FnOnce(&Chunk) -> Option<T>) -> Option<T> {
+        if self.lazy_chunks {
+            return f(self.lazy_chunk(pos.chunk_in_region())?);
+        }
+        if !self.loaded_regions.contains_key(&pos.region_in_world()) {
+            self.load_region(pos.region_in_world())?;
+        }
+        f(self.loaded_regions.get(&pos.region_in_world())?.get_chunk(pos.chunk_in_region())?)
+    }
+
     pub fn get_chunk(&mut self, pos: Position) -> Option<Chunk> {
+        if self.lazy_chunks {
+            return self.lazy_chunk(pos.chunk_in_region()).cloned();
+        }
         let region = if let Some(region) = self.loaded_regions.get(&pos.region_in_world()) {
             region
         } else {
             self.load_region(pos.region_in_world())?;
             self.loaded_regions.get(&pos.region_in_world())?
         };
-        region.get_chunk(pos).cloned()
+        region.get_chunk(pos.chunk_in_region()).cloned()
+    }
+
+    /// Borrows the chunk containing the block position, but only if it finished generating, loading its region (or
+    /// chunk in lazy mode) if needed.
+    pub fn get_full_chunk(&mut self, pos: Position) -> Option<&Chunk> {
+        let chunk = if self.lazy_chunks {
+            self.lazy_chunk(pos.chunk_in_region())?
+        } else {
+            if !self.loaded_regions.contains_key(&pos.region_in_world()) {
+                self.load_region(pos.region_in_world())?;
+            }
+            self.loaded_regions.get(&pos.region_in_world())?.get_chunk(pos.chunk_in_region())?
+        };
+        chunk.is_finished().then_some(chunk)
+    }
+
+    /// Positions of the regions currently kept in the cache
+    pub fn loaded_region_positions(&self) -> Vec<Position> {
+        self.loaded_regions.keys().copied().collect()
+    }
+
+    pub fn is_region_loaded(&self, pos: Position) -> bool {
+        self.loaded_regions.contains_key(&pos)
+    }
+
+    /// Sets a block in the loaded world, loading its region (or chunk in lazy mode) if needed.
+    ///
+    /// Returns false if the chunk doesn't exist or the position is outside of its height. Edits only live in memory
+    /// until [`World::save`] writes them, the changed chunks are tracked in [`World::dirty_chunks`].
+    pub fn set_block(&mut self, pos: Position, block: Block) -> bool {
+        let chunk_pos = pos.chunk_in_region();
+        let chunk = if self.lazy_chunks {
+            self.lazy_chunk(chunk_pos);
+            self.loaded_chunks.get_mut(&chunk_pos).and_then(|chunk| chunk.as_mut())
+        } else {
+            if !self.loaded_regions.contains_key(&pos.region_in_world()) {
+                self.load_region(pos.region_in_world());
+            }
+            self.loaded_regions.get_mut(&pos.region_in_world()).and_then(|region| region.get_chunk_mut(chunk_pos))
+        };
+        let changed = chunk.map_or(false, |chunk| chunk.set(pos, block));
+        if changed {
+            self.dirty_chunks.insert(chunk_pos);
+        }
+        changed
+    }
+
+    /// Chunk positions of the chunks edited since they were loaded
+    pub fn dirty_chunks(&self) -> Vec<Position> {
+        self.dirty_chunks.iter().copied().collect()
+    }
+
+    /// Writes the chunks edited through [`World::set_block`] back to their regions, leaving the other chunks in them
+    /// as they're stored.
+    ///
+    /// The other chunks of each region with edits are copied from the stored region without decompressing them (see
+    /// [`Region::parse_stored`]), while the edited chunks are encoded with [`Chunk::to_nbt`] and get the current time
+    /// as their timestamp. Regions are saved one at a time, if one fails the regions not saved yet keep their chunks
+    /// in [`World::dirty_chunks`].
+    pub fn save(&mut self) -> Result<()> {
+        let mut dirty_by_region: BTreeMap<Position, Vec<Position>> = BTreeMap::new();
+        for chunk_pos in &self.dirty_chunks {
+            dirty_by_region.entry(Position::new(chunk_pos.x>>5, 0, chunk_pos.z>>5)).or_default().push(*chunk_pos);
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs() as u32);
+        for (region_pos, chunk_positions) in dirty_by_region {
+            let mut region = Region::parse_stored(&self.regions.read_region(region_pos)?)?;
+            for chunk_pos in &chunk_positions {
+                let chunk = self.loaded_regions.get(&region_pos)
+                    .and_then(|region| region.get_chunk(*chunk_pos))
+                    .or_else(|| self.loaded_chunks.get(chunk_pos)?.as_ref());
+                if let Some(chunk) = chunk {
+                    let local = Position::new(chunk_pos.x.rem_euclid(32), 0, chunk_pos.z.rem_euclid(32));
+                    region.insert_chunk(local, chunk.clone())?;
+                    region.set_timestamp(local.x, local.z, now);
+                }
+            }
+            let mut region_data = vec![];
+            region.write_to(&mut region_data)?;
+            self.regions.write_region(region_pos, &region_data)?;
+            self.dirty_chunks.retain(|chunk_pos| !chunk_positions.contains(chunk_pos));
+        }
+        Ok(())
+    }
+
+    /// Returns the positions of all regions in the region source, by default the region files in the region
+    /// directory.
+    pub fn region_coords(&self) -> Vec<Position> {
+        self.regions.region_positions()
+    }
+
+    /// Replaces where regions are read from, e.g. to read a world from an archive. The cached regions and chunks
+    /// are dropped, including unsaved edits.
+    pub fn set_region_source(&mut self, source: Box<dyn RegionSource>) {
+        self.regions = source;
+        self.loaded_regions.clear();
+        self.unavailable_regions.clear();
+        self.loaded_chunks.clear();
+        self.dirty_chunks.clear();
+    }
+
+    /// Iterates over every block in the world, one region at a time.
+    ///
+    /// Regions that aren't cached are loaded when reached and their chunks dropped once iterated, so at most one of
+    /// them is kept in memory. Cached regions stay in the cache with their unsaved edits, only their chunks are
+    /// copied when reached. Regions that fail to parse are skipped.
+    pub fn iter_all_blocks(&self) -> impl Iterator<Item = (Position, Block)> + '_ {
+        self.region_coords().into_iter()
+            .filter_map(move |pos| match self.loaded_regions.get(&pos) {
+                Some(region) => Some(region.chunks().clone()),
+                None => Some(self.read_uncached_region(pos)?.into_chunks()),
+            })
+            .flatten()
+            .flat_map(|chunk| {
+                let chunk_pos = *chunk.chunk_pos();
+                chunk.into_sections().into_iter().flat_map(move |section| {
+                    (0..4096).map(move |i| {
+                        let local = Position::new(i%16, i/256, (i/16)%16);
+                        let pos = Position::new(chunk_pos.x*16 + local.x, section.y() as i32*16 + local.y, chunk_pos.z*16 + local.z);
+                        (pos, section.get(local))
+                    })
+                })
+            })
+    }
+
+    /// Reads every region file in parallel and runs `map` on each of them, returning the results in no
+    /// particular order.
+    ///
+    /// Regions are parsed from disk even if they are cached, and dropped once mapped. Regions that fail to parse
+    /// are skipped.
+    #[cfg(feature = "rayon")]
+    pub fn par_scan<T: Send>(&self, map: impl Fn(Position, &Region) -> T + Sync) -> Vec<T> {
+        self.region_coords().into_par_iter()
+            .filter_map(|pos| match self.read_region(pos) {
+                Ok(region) => Some(map(pos, &region)),
+                Err(e) => {
+                    error!("Error parsing region r.{}.{}.mca: {}", pos.x, pos.z, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Calls the visitor for every block inside `bbox`, region by region and chunk by chunk.
+    ///
+    /// Regions that are already cached are used as is, others are loaded just for the visit and dropped afterwards.
+    /// Missing chunks and sections are skipped.
+    pub fn visit_blocks(&mut self, bbox: BoundingBox, visitor: &mut impl BlockVisitor) {
+        let min_region = bbox.min.region_in_world();
+        let max_region = bbox.max.region_in_world();
+        for region_x in min_region.x..=max_region.x {
+            for region_z in min_region.z..=max_region.z {
+                self.with_region(Position::new(region_x, 0, region_z), |region| {
+                    for chunk in region.chunks() {
+                        // Only the height of the chunk itself, lookups outside of it would be skipped anyways
+                        let chunk_pos = chunk.chunk_pos();
+                        let chunk_bbox = BoundingBox::new(
+                            Position::new(chunk_pos.x*16, chunk.min_section_y() as i32*16, chunk_pos.z*16),
+                            Position::new(chunk_pos.x*16 + 15, chunk.max_section_y() as i32*16 + 15, chunk_pos.z*16 + 15),
+                        );
+                        let clipped = match bbox.intersection(&chunk_bbox) {
+                            Some(clipped) => clipped,
+                            None => continue,
+                        };
+                        for y in clipped.min.y..=clipped.max.y {
+                            for z in clipped.min.z..=clipped.max.z {
+                                for x in clipped.min.x..=clipped.max.x {
+                                    let pos = Position::new(x, y, z);
+                                    if let Some(block) = chunk.get_ref(pos) {
+                                        visitor.visit(pos, block);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    /// Calls `f` with the position and a borrow of every present chunk in the regions inside `region_bounds`, given
+    /// in region coordinates with y ignored.
+    ///
+    /// Like [`World::visit_blocks`], cached regions are used as is and others are only loaded for the walk.
+    pub fn walk_chunks(&mut self, region_bounds: BoundingBox, mut f: impl FnMut(Position, &Chunk)) {
+        for region_x in region_bounds.min.x..=region_bounds.max.x {
+            for region_z in region_bounds.min.z..=region_bounds.max.z {
+                self.with_region(Position::new(region_x, 0, region_z), |region| {
+                    for chunk in region.chunks() {
+                        f(*chunk.chunk_pos(), chunk);
+                    }
+                });
+            }
+        }
+    }
+
+    /// Counts the blocks that aren't air in every region, e.g. to report progress or draw a density map.
+    ///
+    /// Like [`World::iter_all_blocks`], cached regions are counted with their unsaved edits and others are only
+    /// loaded for counting. Regions that fail to parse are left out.
+    pub fn count_blocks_by_region(&self) -> BTreeMap<Position, u32> {
+        let mut counts = BTreeMap::new();
+        for pos in self.region_coords() {
+            if let Some(count) = self.with_region(pos, |region| region.chunks().iter().map(|chunk| chunk.block_count()).sum::<u32>()) {
+                counts.insert(pos, count);
+            }
+        }
+        counts
+    }
+
+    /// Counts the chunks overlapping `bbox` (in block coordinates, y is ignored) by their `DataVersion`, e.g. to
+    /// find the parts of a world that were last saved by an older version.
+    pub fn data_version_histogram(&mut self, bbox: BoundingBox) -> BTreeMap<i32, usize> {
+        let region_bounds = BoundingBox::new(bbox.min.region_in_world(), bbox.max.region_in_world());
+        let min_chunk = bbox.min.chunk_in_region();
+        let max_chunk = bbox.max.chunk_in_region();
+        let mut histogram = BTreeMap::new();
+        self.walk_chunks(region_bounds, |pos, chunk| {
+            if (min_chunk.x..=max_chunk.x).contains(&pos.x) && (min_chunk.z..=max_chunk.z).contains(&pos.z) {
+                *histogram.entry(chunk.data_version()).or_insert(0) += 1;
+            }
+        });
+        histogram
+    }
+
+    /// Runs `f` on the region, using the cached version if there is one and otherwise loading it without caching it.
+    fn with_region<T>(&self, pos: Position, f: impl FnOnce(&Region) -> T) -> Option<T> {
+        if let Some(region) = self.loaded_regions.get(&pos) {
+            return Some(f(region));
+        }
+        Some(f(&self.read_uncached_region(pos)?))
+    }
+
+    /// Reads a region without caching it, logging why if it fails to parse. Missing regions are just `None`.
+    fn read_uncached_region(&self, pos: Position) -> Option<Region> {
+        match self.read_region(pos) {
+            Ok(region) => Some(region),
+            Err(McaParseError::WorldLoadError(_)) => None,
+            Err(e) => {
+                error!("Error parsing region: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Finds the datapack and mod dimensions stored under `dimensions/<namespace>/<path>`, returning their
+    /// `namespace:path` identifiers together with their region directories.
+    pub fn custom_dimensions(&self) -> Vec<(String, PathBuf)> {
+        Self::find_custom_dimensions(&self.world_path)
+    }
+
+    fn find_custom_dimensions(world_path: &Path) -> Vec<(String, PathBuf)> {
+        let mut dimensions = vec![];
+        let namespaces = match fs::read_dir(world_path.join("dimensions")) {
+            Ok(namespaces) => namespaces,
+            Err(_) => return dimensions,
+        };
+        for namespace in namespaces.filter_map(|e| e.ok()).filter(|e| e.path().is_dir()) {
+            Self::find_dimensions(&namespace.path(), &namespace.file_name().to_string_lossy(), "", &mut dimensions);
+        }
+        dimensions.sort();
+        dimensions
     }
 
+    /// Whether the world has region files for a dimension other than the overworld, either the vanilla `DIM-1` and
+    /// `DIM1` folders or the datapack dimensions.
+    fn has_other_dimensions(world_dir: &[DirEntry], world_path: &Path) -> bool {
+        world_dir.iter()
+            .filter(|e| e.file_name().to_string_lossy().starts_with("DIM"))
+            .any(|e| e.path().join("region").is_dir())
+            || !Self::find_custom_dimensions(world_path).is_empty()
+    }
+
+    /// Walks a dimension namespace directory looking for region directories, dimension paths can be nested.
+    fn find_dimensions(dir: &Path, namespace: &str, path: &str, dimensions: &mut Vec<(String, PathBuf)>) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.filter_map(|e| e.ok()).filter(|e| e.path().is_dir()) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name == "region" && !path.is_empty() {
+                dimensions.push((format!("{}:{}", namespace, path), entry.path()));
+            } else {
+                let path = if path.is_empty() { name } else { format!("{}/{}", path, name) };
+                Self::find_dimensions(&entry.path(), namespace, &path, dimensions);
+            }
+        }
+    }
+
+    /// Gets a chunk by chunk position, reading just that chunk from its region file the first time.
+    fn lazy_chunk(&mut self, chunk_pos: Position) -> Option<&Chunk> {
+        if !self.loaded_chunks.contains_key(&chunk_pos) {
+            let chunk = self.read_single_chunk(chunk_pos);
+            self.loaded_chunks.insert(chunk_pos, chunk);
+        }
+        self.loaded_chunks.get(&chunk_pos)?.as_ref()
+    }
+
+    fn read_single_chunk(&self, chunk_pos: Position) -> Option<Chunk> {
+        match self.regions.read_chunk(chunk_pos, &self.parse_options) {
+            Ok(chunk) => chunk,
+            // Missing region files are just ungenerated terrain
+            Err(McaParseError::WorldLoadError(_)) => None,
+            Err(e) => {
+                error!("Error parsing chunk {}: {}", chunk_pos, e);
+                None
+            }
+        }
+    }
+
+    /// Re-reads a region from its source and replaces the cached version, e.g. after a running server saved it.
+    ///
+    /// Unsaved edits to chunks in the region are dropped with the cached version, and their chunks are taken out of
+    /// [`World::dirty_chunks`]. Call [`World::save`] first to keep them.
+    pub fn reload_region(&mut self, pos: Position) -> Result<()> {
+        let region = self.read_region(pos)?;
+        self.loaded_regions.insert(pos, region);
+        self.unavailable_regions.remove(&pos);
+        self.loaded_chunks.retain(|chunk_pos, _| Position::new(chunk_pos.x>>5, 0, chunk_pos.z>>5) != pos);
+        self.dirty_chunks.retain(|chunk_pos| Position::new(chunk_pos.x>>5, 0, chunk_pos.z>>5) != pos);
+        Ok(())
+    }
+
+    fn read_region(&self, pos: Position) -> Result<Region> {
+        Region::parse_region_with_options(self.regions.read_region(pos)?, &self.parse_options)
+    }
+
+    /// Loads a region into the cache, remembering regions that can't be loaded until the next reload.
     fn load_region(&mut self, pos: Position) -> Option<()> {
-        debug!("Loading region: r.{}.{}.mca", pos.x, pos.z);
-        let region_data = fs::read(self.region_path.as_path().join(format!("r.{}.{}.mca", pos.x, pos.z))).ok()?;
-        let region = Region::parse_region(region_data);
-        if region.is_err() {
-            error!("Error parsing region: {}", region.err().unwrap());
+        if self.unavailable_regions.contains(&pos) {
             return None;
         }
-        self.loaded_regions.insert(pos, region.ok()?);
+        let region = match self.read_region(pos) {
+            Ok(region) => region,
+            Err(e) => {
+                // Missing region files are just ungenerated terrain
+                if !matches!(e, McaParseError::WorldLoadError(_)) {
+                    error!("Error parsing region: {}", e);
+                }
+                self.unavailable_regions.insert(pos);
+                return None;
+            }
+        };
+        self.loaded_regions.insert(pos, region);
         Some(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,158 @@
+//! Bundled vanilla block knowledge, enabled by the `block_tables` feature.
+
+/// Block families that never fill their whole block space or let light through, matched on the identifier suffix
+const NON_FULL_SUFFIXES: &[&str] = &[
+    "_slab", "_stairs", "_fence", "_fence_gate", "_wall", "_door", "_trapdoor", "_pane", "_glass", "_leaves",
+    "_carpet", "_button", "_pressure_plate", "_sign", "_banner", "_bed", "_candle", "_candle_cake", "_sapling",
+    "_tulip", "_coral", "_coral_fan", "_wall_fan", "_rail", "_torch", "_head", "_skull", "_mushroom", "_fungus",
+    "_vines", "_plant", "_bud", "_cluster", "_shulker_box", "_grate", "_anvil", "_cauldron", "_lantern",
+    "_campfire", "_egg", "_chain", "_bars", "_lightning_rod",
+];
+
+/// Full cubes whose identifier ends with one of the suffixes above
+const FULL_SUFFIX_EXCEPTIONS: &[&str] = &["sea_lantern", "jack_o_lantern"];
+
+/// Single blocks that never fill their whole block space or let light through. Mud and soul sand aren't part of it,
+/// their collision box is lower but they're still drawn and occlude their neighbors as full cubes.
+const NON_FULL_BLOCKS: &[&str] = &[
+    "air", "cave_air", "void_air", "water", "lava", "bubble_column", "fire", "soul_fire", "glass", "tinted_glass",
+    "ice", "frosted_ice", "slime_block", "honey_block", "barrier", "light", "structure_void", "spawner",
+    "trial_spawner", "vault", "beacon", "conduit", "chest", "trapped_chest", "ender_chest", "enchanting_table",
+    "end_portal_frame", "end_portal", "nether_portal", "end_gateway", "brewing_stand", "cauldron", "hopper",
+    "anvil", "lectern", "grindstone", "stonecutter", "bell", "lantern", "campfire", "cake", "candle", "composter",
+    "daylight_detector", "dirt_path", "farmland", "snow", "scaffolding", "ladder", "lever",
+    "redstone_wire", "repeater", "comparator", "tripwire", "tripwire_hook", "cobweb", "grass", "short_grass",
+    "tall_grass", "fern", "large_fern", "dead_bush", "seagrass", "tall_seagrass", "kelp", "sugar_cane", "bamboo",
+    "cactus", "vine", "lily_pad", "sea_pickle", "flower_pot", "decorated_pot", "end_rod", "lightning_rod",
+    "chorus_flower", "azalea", "flowering_azalea", "big_dripleaf", "big_dripleaf_stem", "small_dripleaf",
+    "pointed_dripstone", "sculk_sensor", "calibrated_sculk_sensor", "sculk_shrieker", "sculk_vein", "glow_lichen",
+    "hanging_roots", "spore_blossom", "frogspawn", "pitcher_plant", "pitcher_crop", "torchflower",
+    "torchflower_crop", "piston_head", "moving_piston", "heavy_core", "rail", "torch", "iron_bars", "chain",
+    "shulker_box", "dandelion", "poppy", "blue_orchid", "allium", "azure_bluet", "oxeye_daisy", "cornflower",
+    "lily_of_the_valley", "wither_rose", "sunflower", "lilac", "rose_bush", "peony", "wheat", "carrots",
+    "potatoes", "beetroots", "melon_stem", "pumpkin_stem", "attached_melon_stem", "attached_pumpkin_stem",
+    "crimson_roots", "warped_roots", "mangrove_roots", "nether_wart", "cocoa", "sweet_berry_bush", "cave_vines",
+    "nether_sprouts", "pink_petals", "leaf_litter", "wildflowers", "bush", "firefly_bush", "mangrove_propagule",
+    "pale_hanging_moss", "open_eyeblossom", "closed_eyeblossom", "resin_clump", "short_dry_grass", "tall_dry_grass",
+    "cactus_flower",
+];
+
+/// Whether a block is a full cube that hides the faces of all its neighbors.
+///
+/// Only knows vanilla blocks, blocks from other namespaces are never considered opaque.
+pub(crate) fn is_opaque_full_cube(identifier: &str) -> bool {
+    let name = match identifier.strip_prefix("minecraft:") {
+        Some(name) => name,
+        None => return false,
+    };
+    !name.starts_with("potted_")
+        && !NON_FULL_BLOCKS.contains(&name)
+        && (FULL_SUFFIX_EXCEPTIONS.contains(&name) || !NON_FULL_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)))
+}
+
+/// Properties that have the same default value on every vanilla block that has them
+const SHARED_DEFAULT_PROPERTIES: &[(&str, &str)] = &[
+    ("waterlogged", "false"), ("powered", "false"), ("snowy", "false"), ("triggered", "false"),
+    ("occupied", "false"), ("open", "false"), ("in_wall", "false"), ("persistent", "false"), ("locked", "false"),
+    ("enabled", "true"), ("inverted", "false"), ("attached", "false"), ("disarmed", "false"), ("hanging", "false"),
+];
+
+/// Default values of properties that differ between blocks, for commonly written blocks
+const BLOCK_DEFAULT_PROPERTIES: &[(&str, &[(&str, &str)])] = &[
+    ("note_block", &[("instrument", "harp"), ("note", "0")]),
+    ("redstone_wire", &[("power", "0"), ("north", "none"), ("east", "none"), ("south", "none"), ("west", "none")]),
+    ("repeater", &[("delay", "1"), ("facing", "north")]),
+    ("comparator", &[("facing", "north"), ("mode", "compare")]),
+    ("farmland", &[("moisture", "0")]),
+    ("water", &[("level", "0")]),
+    ("lava", &[("level", "0")]),
+    ("furnace", &[("facing", "north"), ("lit", "false")]),
+    ("blast_furnace", &[("facing", "north"), ("lit", "false")]),
+    ("smoker", &[("facing", "north"), ("lit", "false")]),
+    ("redstone_ore", &[("lit", "false")]),
+    ("deepslate_redstone_ore", &[("lit", "false")]),
+    ("redstone_lamp", &[("lit", "false")]),
+    ("redstone_torch", &[("lit", "true")]),
+    ("wheat", &[("age", "0")]),
+    ("carrots", &[("age", "0")]),
+    ("potatoes", &[("age", "0")]),
+    ("beetroots", &[("age", "0")]),
+    ("sugar_cane", &[("age", "0")]),
+    ("cactus", &[("age", "0")]),
+    ("snow", &[("layers", "1")]),
+    ("oak_log", &[("axis", "y")]),
+    ("spruce_log", &[("axis", "y")]),
+    ("birch_log", &[("axis", "y")]),
+    ("jungle_log", &[("axis", "y")]),
+    ("acacia_log", &[("axis", "y")]),
+    ("dark_oak_log", &[("axis", "y")]),
+    ("mangrove_log", &[("axis", "y")]),
+    ("cherry_log", &[("axis", "y")]),
+    ("basalt", &[("axis", "y")]),
+    ("hay_block", &[("axis", "y")]),
+    ("bone_block", &[("axis", "y")]),
+];
+
+/// The vanilla default value of a property, if it's in the bundled tables.
+///
+/// Only knows vanilla blocks, and not every property of them, unknown properties return `None`.
+pub(crate) fn default_property(identifier: &str, property: &str) -> Option<&'static str> {
+    let name = identifier.strip_prefix("minecraft:")?;
+    let block_default = BLOCK_DEFAULT_PROPERTIES.iter()
+        .find(|(block, _)| *block == name)
+        .and_then(|(_, properties)| properties.iter().find(|(p, _)| *p == property));
+    block_default.or_else(|| SHARED_DEFAULT_PROPERTIES.iter().find(|(p, _)| *p == property))
+        .map(|(_, value)| *value)
+}
+
+/// Block families that store extra data in a block entity, matched on the identifier suffix
+const BLOCK_ENTITY_SUFFIXES: &[&str] = &["_sign", "_banner", "_bed", "_shulker_box"];
+
+/// Single blocks that store extra data in a block entity
+const BLOCK_ENTITY_BLOCKS: &[&str] = &[
+    "chest", "trapped_chest", "ender_chest", "barrel", "shulker_box", "furnace", "blast_furnace", "smoker",
+    "hopper", "dispenser", "dropper", "crafter", "brewing_stand", "beacon", "conduit", "bell", "lectern",
+    "jukebox", "enchanting_table", "chiseled_bookshelf", "decorated_pot", "campfire", "soul_campfire", "beehive",
+    "bee_nest", "spawner", "trial_spawner", "vault", "end_portal", "end_gateway", "moving_piston", "comparator",
+    "daylight_detector", "command_block", "chain_command_block", "repeating_command_block", "structure_block",
+    "jigsaw", "test_block", "test_instance_block", "sculk_sensor", "calibrated_sculk_sensor", "sculk_catalyst",
+    "sculk_shrieker", "suspicious_sand", "suspicious_gravel", "creaking_heart", "skeleton_skull",
+    "skeleton_wall_skull", "wither_skeleton_skull", "wither_skeleton_wall_skull", "zombie_head", "zombie_wall_head",
+    "player_head", "player_wall_head", "creeper_head", "creeper_wall_head", "dragon_head", "dragon_wall_head",
+    "piglin_head", "piglin_wall_head",
+];
+
+/// Whether a block has a block entity, i.e. an entry in the chunk's `block_entities`.
+///
+/// Only knows vanilla blocks, blocks from other namespaces never have one.
+pub(crate) fn has_block_entity(identifier: &str) -> bool {
+    let name = match identifier.strip_prefix("minecraft:") {
+        Some(name) => name,
+        None => return false,
+    };
+    BLOCK_ENTITY_BLOCKS.contains(&name) || BLOCK_ENTITY_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// Blocks that were renamed, with the data version of the release that renamed them
+const RENAMES: &[(i32, &str, &str)] = &[
+    (1952, "sign", "oak_sign"),
+    (1952, "wall_sign", "oak_wall_sign"),
+    (2724, "grass_path", "dirt_path"),
+    (3698, "grass", "short_grass"),
+];
+
+/// The identifier a block has in the version with `target_data_version`, or `None` if it's the same.
+///
+/// Renames are applied forwards when the target is at or after the rename, and backwards when it's before it.
+pub(crate) fn migrated_identifier(identifier: &str, target_data_version: i32) -> Option<String> {
+    let mut name = identifier.strip_prefix("minecraft:")?;
+    for (data_version, old, new) in RENAMES {
+        if target_data_version >= *data_version && name == *old {
+            name = *new;
+        } else if target_data_version < *data_version && name == *new {
+            name = *old;
+        }
+    }
+    let migrated = format!("minecraft:{}", name);
+    (migrated != identifier).then_some(migrated)
+}
@@ -1,45 +1,330 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::OnceLock;
 use inbt::NbtTag;
 use log::{trace, warn};
-use crate::{Block, Position};
+use crate::{Block, BlockQueryError, BoundingBox, McaParseError, Position, Result};
 use crate::parser::section::Section;
 use crate::section::BlockIDGetter;
 
+/// The generation stages of a chunk, ordered like the vanilla generation pipeline.
+///
+/// Includes the stages of every version since 1.18, even though some were added or removed since then.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChunkStatus {
+    Empty,
+    StructureStarts,
+    StructureReferences,
+    Biomes,
+    Noise,
+    Surface,
+    Carvers,
+    /// Only used before 1.20
+    LiquidCarvers,
+    Features,
+    /// Only used since 1.20
+    InitializeLight,
+    Light,
+    Spawn,
+    /// Only used before 1.20
+    Heightmaps,
+    Full,
+}
+
+impl ChunkStatus {
+    /// Parses a status as stored in chunks, with or without the `minecraft:` namespace.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let status = match name.strip_prefix("minecraft:").unwrap_or(name) {
+            "empty" => Self::Empty,
+            "structure_starts" => Self::StructureStarts,
+            "structure_references" => Self::StructureReferences,
+            "biomes" => Self::Biomes,
+            "noise" => Self::Noise,
+            "surface" => Self::Surface,
+            "carvers" => Self::Carvers,
+            "liquid_carvers" => Self::LiquidCarvers,
+            "features" => Self::Features,
+            "initialize_light" => Self::InitializeLight,
+            "light" => Self::Light,
+            "spawn" => Self::Spawn,
+            "heightmaps" => Self::Heightmaps,
+            "full" => Self::Full,
+            _ => return None,
+        };
+        Some(status)
+    }
+}
+
+/// Names of the chunk fields, which depend on the version that saved the chunk.
+pub(crate) struct ChunkKeys {
+    pub(crate) sections: &'static str,
+    pub(crate) status: &'static str,
+    pub(crate) x_pos: &'static str,
+    pub(crate) y_pos: &'static str,
+    pub(crate) z_pos: &'static str,
+}
+
+/// The chunk keys by the first data version using them, oldest first
+static CHUNK_KEYS: [(i32, ChunkKeys); 2] = [
+    (0, ChunkKeys { sections: "Sections", status: "Status", x_pos: "xPos", y_pos: "yPos", z_pos: "zPos" }),
+    // 21w43a moved everything out of the Level compound and renamed the sections
+    (Chunk::NO_LEVEL_DATA_VERSION, ChunkKeys { sections: "sections", status: "Status", x_pos: "xPos", y_pos: "yPos", z_pos: "zPos" }),
+];
+
+impl ChunkKeys {
+    /// Picks the keys for a data version. Chunks without one are told apart by whether they have a `Level` compound.
+    pub(crate) fn for_version(data_version: i32, has_level: bool) -> &'static ChunkKeys {
+        if data_version == Chunk::UNKNOWN_DATA_VERSION {
+            let (_, keys) = if has_level { &CHUNK_KEYS[0] } else { &CHUNK_KEYS[CHUNK_KEYS.len() - 1] };
+            return keys;
+        }
+        let (_, keys) = CHUNK_KEYS.iter().rev()
+            .find(|(first_version, _)| *first_version <= data_version)
+            .unwrap_or(&CHUNK_KEYS[0]);
+        keys
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Chunk {
     data_version: i32,
     chunk_pos: Position,
     status: String,
     sections: Vec<Section>,
+    /// Highest section Y, kept separately since sections that are only air can be dropped
+    max_section_y: i8,
+    /// The raw chunk NBT, kept around for the tags that aren't parsed into fields. The parsed sections are taken out
+    /// of it so their blocks and light aren't stored twice. Not present for fabricated chunks.
+    nbt: Option<NbtTag>,
 }
 
 impl Chunk {
-    pub fn new(data_version: i32, chunk_pos: Position, status: String, sections: Vec<Section>) -> Self {
-        Self {
+    /// Data version used for chunks that don't store one
+    pub const UNKNOWN_DATA_VERSION: i32 = 0;
+    /// First data version using the flattened block states (17w47a), older chunks store numeric block ids
+    pub const FLATTENING_DATA_VERSION: i32 = 1451;
+    /// First data version without the `Level` compound (21w43a), which also renamed `Sections` to `sections`
+    pub const NO_LEVEL_DATA_VERSION: i32 = 2844;
+
+    /// Creates a chunk from its parsed fields and the NBT they were parsed from. The entries of `sections` are
+    /// removed from the sections stored in `nbt`, only the ones without blocks (like light-only ones) are kept.
+    pub fn new(data_version: i32, chunk_pos: Position, status: String, sections: Vec<Section>, nbt: NbtTag) -> Self {
+        let max_section_y = sections.iter().map(|s| s.y()).max().unwrap_or(chunk_pos.y as i8);
+        let mut chunk = Self {
             data_version,
             chunk_pos,
             status,
             sections,
+            max_section_y,
+            nbt: Some(nbt),
+        };
+        let parsed = chunk.sections.iter().map(|section| section.y()).collect::<BTreeSet<i8>>();
+        if let Some((stored, _)) = chunk.stored_sections_mut() {
+            stored.retain(|tag| Self::section_y(tag).map_or(true, |y| !parsed.contains(&y)));
+        }
+        chunk
+    }
+
+    /// Creates a fully generated chunk filled with air, with sections from `min_section` to `max_section` inclusive.
+    pub fn new_empty(pos: Position, min_section: i8, max_section: i8, data_version: i32) -> Self {
+        Self {
+            data_version,
+            chunk_pos: Position::new(pos.x, min_section as i32, pos.z),
+            status: "minecraft:full".to_string(),
+            sections: (min_section..=max_section).map(Section::empty).collect(),
+            max_section_y: max_section,
+            nbt: None,
         }
     }
     /// Gets block relative to chunk origin
     pub fn get(&self, pos: Position) -> Option<Block> {
-        let section = pos.section_index_in_chunk();
-        if section.is_none() {
+        self.get_ref(pos).cloned()
+    }
+
+    /// Same as [`Chunk::get`], but borrows the block instead of cloning it
+    ///
+    /// Positions in sections that aren't stored, e.g. because they were dropped as empty or the version that saved
+    /// the chunk left them out, are air as long as they're inside of the chunk's height, like with
+    /// [`Chunk::try_get`].
+    pub fn get_ref(&self, pos: Position) -> Option<&Block> {
+        match self.section_at(pos) {
+            Some(section) => Some(section.get_ref(pos)),
+            None if (self.min_section_y() as i32..=self.max_section_y as i32).contains(&(pos.y>>4)) => Some(Self::air()),
+            None => None,
+        }
+    }
+
+    fn air() -> &'static Block {
+        static AIR: OnceLock<Block> = OnceLock::new();
+        AIR.get_or_init(Block::default)
+    }
+
+    /// Same as [`Chunk::get_ref`], but tells why there's no block instead of logging a warning and returning `None`
+    ///
+    /// Sections that aren't stored are air like with [`Chunk::get_ref`], so every position inside of the chunk's
+    /// height gives a block.
+    pub fn try_get(&self, pos: Position) -> Result<Option<&Block>, BlockQueryError> {
+        let section_y = pos.y>>4;
+        if section_y < self.min_section_y() as i32 || section_y > self.max_section_y() as i32 {
+            return Err(BlockQueryError::OutOfVerticalRange(pos.y));
+        }
+        Ok(self.get_ref(pos))
+    }
+
+    /// Block light (0-15) relative to chunk origin, 0 where the chunk has no light stored
+    pub fn get_block_light(&self, pos: Position) -> Option<u8> {
+        self.light_at(pos, Section::block_light)
+    }
+
+    /// Sky light (0-15) relative to chunk origin, 0 where the chunk has no light stored
+    pub fn get_sky_light(&self, pos: Position) -> Option<u8> {
+        self.light_at(pos, Section::sky_light)
+    }
+
+    fn light_at(&self, pos: Position, light: impl Fn(&Section, Position) -> Option<u8>) -> Option<u8> {
+        let section_y = pos.y>>4;
+        if section_y < self.min_section_y() as i32 || section_y > self.max_section_y as i32 {
+            return None;
+        }
+        Some(self.sections.iter().find(|s| s.y() as i32 == section_y).and_then(|s| light(s, pos)).unwrap_or(0))
+    }
+
+    /// Positions (in world coordinates) where hostile mobs could spawn: an air block with air above it, on top of a
+    /// block with a solid top, with a block light of at most `max_light`.
+    ///
+    /// Sky light is left out since it depends on the time of day, and the mob specific rules (like slimes or
+    /// spawning in water) aren't taken into account. Solid tops are opaque full cubes and top or double slabs.
+    #[cfg(feature = "block_tables")]
+    pub fn spawnable_positions(&self, max_light: u8) -> Vec<Position> {
+        let has_solid_top = |block: &Block| {
+            block.is_opaque_full_cube()
+                || (block.identifier().ends_with("_slab") && block.properties().get("type").map_or(false, |t| t != "bottom"))
+        };
+        let min_y = self.min_section_y() as i32*16;
+        let max_y = self.max_section_y as i32*16 + 15;
+        let is_air = |pos: Position| self.get_ref(pos).map_or(false, |block| block.is_air());
+        let mut positions = vec![];
+        for (x, z) in Position::chunk_columns(self.chunk_pos) {
+            for y in min_y + 1..=max_y {
+                let pos = Position::new(x, y, z);
+                // Above the top of the chunk is always air
+                let spawnable = is_air(pos)
+                    && (y == max_y || is_air(Position::new(x, y + 1, z)))
+                    && self.get_ref(Position::new(x, y - 1, z)).map_or(false, has_solid_top)
+                    && self.get_block_light(pos).map_or(false, |light| light <= max_light);
+                if spawnable {
+                    positions.push(pos);
+                }
+            }
+        }
+        positions
+    }
+
+    /// Sets block relative to chunk origin, returning false if the position is outside of the chunk's height.
+    ///
+    /// Sections that aren't stored are added as air first.
+    pub fn set(&mut self, pos: Position, block: Block) -> bool {
+        let section_y = pos.y>>4;
+        if section_y < self.min_section_y() as i32 || section_y > self.max_section_y as i32 {
+            return false;
+        }
+        let index = match self.sections.iter().position(|s| s.y() as i32 == section_y) {
+            Some(index) => index,
+            None => {
+                self.sections.push(Section::empty(section_y as i8));
+                self.sections.len() - 1
+            }
+        };
+        self.sections[index].set(pos, block);
+        true
+    }
+
+    /// Gets block relative to chunk origin together with the section containing it
+    pub fn get_with_section(&self, pos: Position) -> Option<(&Section, Block)> {
+        let section = self.section_at(pos)?;
+        Some((section, section.get(pos)))
+    }
+
+    /// Gets the section containing the position, by its section Y instead of assuming the 1.18 world height
+    fn section_at(&self, pos: Position) -> Option<&Section> {
+        let section_y = pos.y>>4;
+        if section_y < self.min_section_y() as i32 || section_y > self.max_section_y as i32 {
             warn!("Warning: section index out of bounds (Original Y: {})", pos.y);
+            return None;
+        }
+        self.sections.iter().find(|s| s.y() as i32 == section_y)
+    }
+
+    /// Collects the identifier of every block in the chunk by merging the section palettes.
+    pub fn distinct_blocks(&self) -> BTreeSet<String> {
+        self.sections.iter()
+            .flat_map(|section| section.palette())
+            .map(|block| block.identifier().clone())
+            .collect()
+    }
+
+    /// Number of blocks in the chunk that aren't air.
+    pub fn block_count(&self) -> u32 {
+        self.sections.iter().map(|section| section.iter_non_air().count() as u32).sum()
+    }
+
+    /// Iterates over every block in the chunk from the bottom up, with x and z relative to the chunk and the world y.
+    pub fn iter_blocks(&self) -> impl Iterator<Item = (Position, &Block)> {
+        let mut sections = self.sections.iter().collect::<Vec<&Section>>();
+        sections.sort_by_key(|s| s.y());
+        sections.into_iter().flat_map(|section| {
+            let section_y = section.y() as i32*16;
+            section.iter_blocks().map(move |(pos, block)| (Position::new(pos.x, section_y + pos.y, pos.z), block))
+        })
+    }
+
+    /// The smallest box (in world coordinates) containing every block that isn't air, or `None` if the chunk is
+    /// only air.
+    pub fn content_bounds(&self) -> Option<BoundingBox> {
+        let mut bounds: Option<BoundingBox> = None;
+        for section in self.sections.iter().filter(|section| !section.is_all_air()) {
+            let origin = Position::new(self.chunk_pos.x*16, section.y() as i32*16, self.chunk_pos.z*16);
+            for (pos, _) in section.iter_non_air() {
+                let pos = Position::new(origin.x + pos.x, origin.y + pos.y, origin.z + pos.z);
+                bounds = Some(match bounds {
+                    Some(b) => BoundingBox::new(
+                        Position::new(b.min.x.min(pos.x), b.min.y.min(pos.y), b.min.z.min(pos.z)),
+                        Position::new(b.max.x.max(pos.x), b.max.y.max(pos.y), b.max.z.max(pos.z)),
+                    ),
+                    None => BoundingBox::new(pos, pos),
+                });
+            }
         }
-        Some(self.sections[section? as usize].get(pos))
+        bounds
     }
 
     /// Returns a vector with chunk data that can be put directly into a chunk data packet
-    pub fn network_data(&self, id_getter: Box<dyn BlockIDGetter>) -> Vec<u8> {
+    ///
+    /// The packet needs one section for every section Y of the dimension, so sections are sent from `min_section`
+    /// to `max_section` (inclusive) in order, with air sections in place of any that are missing from the chunk.
+    pub fn network_data(&self, id_getter: Box<dyn BlockIDGetter>, min_section: i8, max_section: i8) -> Vec<u8> {
         trace!("{} sections", self.sections.len());
-        self.sections.iter().flat_map(|s| s.network_data(&id_getter)).collect()
+        (min_section..=max_section).flat_map(|y| {
+            match self.sections.iter().find(|s| s.y() == y) {
+                Some(section) => section.network_data(&id_getter),
+                None => Section::empty(y).network_data(&id_getter),
+            }
+        }).collect()
     }
 
     pub fn is_finished(&self) -> bool {
         &*self.status == "minecraft:full"
     }
 
+    /// The parsed status, or `None` if it isn't one of the known stages
+    pub fn generation_status(&self) -> Option<ChunkStatus> {
+        ChunkStatus::from_name(&self.status)
+    }
+
+    /// Whether the chunk has reached the given generation stage. Chunks with an unknown status never have.
+    pub fn is_at_least(&self, status: ChunkStatus) -> bool {
+        self.generation_status().map_or(false, |s| s >= status)
+    }
+
     pub fn data_version(&self) -> i32 {
         self.data_version
     }
@@ -55,4 +340,340 @@ impl Chunk {
     pub fn sections(&self) -> &Vec<Section> {
         &self.sections
     }
+
+    /// The sections for building chunks in the crate, which have to stay between the lowest and highest section Y
+    #[cfg(feature = "testutil")]
+    pub(crate) fn sections_mut(&mut self) -> &mut Vec<Section> {
+        &mut self.sections
+    }
+
+    pub fn into_sections(self) -> Vec<Section> {
+        self.sections
+    }
+
+    pub fn section_count(&self) -> usize {
+        self.sections.len()
+    }
+
+    /// Lowest section Y index of this chunk as stored in its `yPos`, e.g. -4 since 1.18
+    pub fn min_section_y(&self) -> i8 {
+        self.chunk_pos.y as i8
+    }
+
+    /// Highest section Y index in this chunk, including sections that were dropped as empty
+    pub fn max_section_y(&self) -> i8 {
+        self.max_section_y
+    }
+
+    /// Removes the sections that are only air, lookups inside of them still return air.
+    ///
+    /// Parsed chunks keep the NBT of the dropped sections without their blocks, so their light and biomes are still
+    /// written by [`Chunk::to_nbt`].
+    pub fn drop_empty_sections(&mut self) {
+        let (empty, kept): (Vec<Section>, Vec<Section>) = std::mem::take(&mut self.sections).into_iter().partition(|section| section.is_all_air());
+        self.sections = kept;
+        let spanning = self.data_version < Section::NON_SPANNING_DATA_VERSION;
+        if let Some((stored, level)) = self.stored_sections_mut() {
+            stored.extend(empty.iter().map(|section| if level { section.to_level_nbt(spanning) } else { section.to_nbt() }));
+        }
+    }
+
+    /// The sections list in the kept NBT, and whether it's inside of a `Level` compound
+    fn stored_sections_mut(&mut self) -> Option<(&mut Vec<NbtTag>, bool)> {
+        let children = match self.nbt.as_mut()? {
+            NbtTag::Compound(_, children) => children,
+            _ => return None,
+        };
+        let level_index = children.iter().position(|tag| matches!(tag, NbtTag::Compound(name, _) if name == "Level"));
+        let keys = ChunkKeys::for_version(self.data_version, level_index.is_some());
+        let tags = match level_index {
+            Some(index) => match &mut children[index] {
+                NbtTag::Compound(_, level) => level,
+                _ => return None,
+            },
+            None => children,
+        };
+        tags.iter_mut().find_map(|tag| match tag {
+            NbtTag::List(name, sections) if name == keys.sections => Some(sections),
+            _ => None,
+        }).map(|sections| (sections, level_index.is_some()))
+    }
+
+    /// The `Y` of a stored section
+    fn section_y(tag: &NbtTag) -> Option<i8> {
+        match tag {
+            NbtTag::Compound(_, tags) => tags.iter().find_map(|tag| match tag {
+                NbtTag::Byte(name, y) if name == "Y" => Some(*y),
+                _ => None,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The NBT the chunk was parsed from, without the sections that were parsed into [`Chunk::sections`]
+    pub fn nbt(&self) -> Option<&NbtTag> {
+        self.nbt.as_ref()
+    }
+
+    /// Returns the chunk in the format it's stored in regions, in the layout of its data version.
+    ///
+    /// When the chunk was parsed, the fields and sections are merged into the original NBT so the tags that aren't
+    /// parsed (entities, heightmaps, ...) are kept. The sections are written with their light and biomes, next to
+    /// the stored sections without blocks like the light-only ones.
+    ///
+    /// Chunks from before 21w43a are written inside of their `Level` compound with the `Palette` and `BlockStates`
+    /// sections of those versions. Chunks from before the flattening stored numeric block ids, which aren't
+    /// written, so they fail with [`McaParseError::UnsupportedVersion`].
+    pub fn to_nbt(&self) -> Result<NbtTag> {
+        if self.data_version != Self::UNKNOWN_DATA_VERSION && self.data_version < Self::FLATTENING_DATA_VERSION {
+            return Err(McaParseError::UnsupportedVersion(self.data_version));
+        }
+        let (name, mut children) = match &self.nbt {
+            Some(NbtTag::Compound(name, children)) => (name.clone(), children.clone()),
+            _ => (String::new(), vec![]),
+        };
+        // Fabricated chunks go by their data version, parsed ones keep the layout they were stored in
+        if self.nbt.is_none() && self.data_version != Self::UNKNOWN_DATA_VERSION && self.data_version < Self::NO_LEVEL_DATA_VERSION {
+            children.push(NbtTag::Compound("Level".to_string(), vec![]));
+        }
+        let level = children.iter_mut().find_map(|tag| match tag {
+            NbtTag::Compound(name, tags) if name == "Level" => Some(tags),
+            _ => None,
+        });
+        let keys = ChunkKeys::for_version(self.data_version, level.is_some());
+        match level {
+            Some(level) => {
+                let spanning = self.data_version < Section::NON_SPANNING_DATA_VERSION;
+                self.set_fields(level, keys, |section| section.to_level_nbt(spanning));
+            }
+            None => {
+                self.set_fields(&mut children, keys, Section::to_nbt);
+                Self::set_child(&mut children, NbtTag::Int(keys.y_pos.to_string(), self.chunk_pos.y));
+            }
+        }
+        Self::set_child(&mut children, NbtTag::Int("DataVersion".to_string(), self.data_version));
+        Ok(NbtTag::Compound(name, children))
+    }
+
+    /// Sets the position, status and sections in the compound holding them under the names in `keys`, merging the
+    /// sections into the ones already stored by their Y.
+    fn set_fields(&self, tags: &mut Vec<NbtTag>, keys: &ChunkKeys, section_nbt: impl Fn(&Section) -> NbtTag) {
+        let mut sections = tags.iter().find_map(|tag| match tag {
+            NbtTag::List(name, sections) if name == keys.sections => Some(sections.clone()),
+            _ => None,
+        }).unwrap_or_default();
+
+        for section in &self.sections {
+            let section_nbt = section_nbt(section);
+            let existing = sections.iter_mut().find(|tag| Self::section_y(tag) == Some(section.y()));
+            match existing {
+                Some(NbtTag::Compound(_, tags)) => {
+                    if let NbtTag::Compound(_, section_tags) = section_nbt {
+                        for tag in section_tags {
+                            Self::set_child(tags, tag);
+                        }
+                    }
+                }
+                _ => sections.push(section_nbt),
+            }
+        }
+
+        Self::set_child(tags, NbtTag::Int(keys.x_pos.to_string(), self.chunk_pos.x));
+        Self::set_child(tags, NbtTag::Int(keys.z_pos.to_string(), self.chunk_pos.z));
+        Self::set_child(tags, NbtTag::String(keys.status.to_string(), self.status.clone()));
+        Self::set_child(tags, NbtTag::List(keys.sections.to_string(), sections));
+    }
+
+    /// Replaces the child with the same name as `tag`, or adds it if there is none
+    fn set_child(children: &mut Vec<NbtTag>, tag: NbtTag) {
+        let name = Self::tag_name(&tag).map(|name| name.to_string());
+        match children.iter_mut().find(|child| name.is_some() && Self::tag_name(child) == name.as_deref()) {
+            Some(child) => *child = tag,
+            None => children.push(tag),
+        }
+    }
+
+    fn tag_name(tag: &NbtTag) -> Option<&str> {
+        match tag {
+            NbtTag::Byte(name, _) | NbtTag::Short(name, _) | NbtTag::Int(name, _) | NbtTag::Long(name, _)
+            | NbtTag::String(name, _) | NbtTag::List(name, _) | NbtTag::Compound(name, _)
+            | NbtTag::LongArray(name, _) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Exports the part of `bbox` inside this chunk in the structure block (`.nbt`) format.
+    ///
+    /// Block positions are relative to the lowest corner of the clipped box. Air is left out unless `include_air`
+    /// is set, in which case structure blocks will also clear the air positions when placing it.
+    pub fn to_structure_nbt(&self, bbox: BoundingBox, include_air: bool) -> NbtTag {
+        let chunk_bbox = BoundingBox::new(
+            Position::new(self.chunk_pos.x*16, self.min_section_y() as i32*16, self.chunk_pos.z*16),
+            Position::new(self.chunk_pos.x*16 + 15, self.max_section_y() as i32*16 + 15, self.chunk_pos.z*16 + 15),
+        );
+        let clipped = bbox.intersection(&chunk_bbox);
+
+        let mut palette: Vec<Block> = vec![];
+        let mut blocks = vec![];
+        if let Some(clipped) = clipped {
+            for y in clipped.min.y..=clipped.max.y {
+                for z in clipped.min.z..=clipped.max.z {
+                    for x in clipped.min.x..=clipped.max.x {
+                        let block = match self.get(Position::new(x, y, z)) {
+                            Some(block) => block,
+                            None => continue,
+                        };
+                        if block.is_air() && !include_air {
+                            continue;
+                        }
+                        let state = match palette.iter().position(|b| b.eq(&block)) {
+                            Some(state) => state,
+                            None => {
+                                palette.push(block);
+                                palette.len() - 1
+                            }
+                        };
+                        blocks.push(NbtTag::Compound(String::new(), vec![
+                            NbtTag::Int("state".to_string(), state as i32),
+                            NbtTag::List("pos".to_string(), vec![
+                                NbtTag::Int(String::new(), x - clipped.min.x),
+                                NbtTag::Int(String::new(), y - clipped.min.y),
+                                NbtTag::Int(String::new(), z - clipped.min.z),
+                            ]),
+                        ]));
+                    }
+                }
+            }
+        }
+
+        let size = clipped.map(|b| b.size()).unwrap_or(Position::new(0, 0, 0));
+        NbtTag::Compound(String::new(), vec![
+            NbtTag::Int("DataVersion".to_string(), self.data_version),
+            NbtTag::List("size".to_string(), vec![
+                NbtTag::Int(String::new(), size.x),
+                NbtTag::Int(String::new(), size.y),
+                NbtTag::Int(String::new(), size.z),
+            ]),
+            NbtTag::List("palette".to_string(), palette.iter().map(|b| b.to_nbt()).collect()),
+            NbtTag::List("blocks".to_string(), blocks),
+            NbtTag::List("entities".to_string(), vec![]),
+        ])
+    }
+
+    /// Returns true if the chunk is being blended with chunks from before the 1.18 world height change.
+    ///
+    /// Blending chunks can also carry `below_zero_retrogen`, which is ignored by the parser.
+    pub fn is_blending(&self) -> bool {
+        self.nbt.as_ref().map_or(false, |nbt| nbt.get("blending_data").is_ok())
+    }
+
+    /// Whether the stored light of the chunk is valid, from the `isLightOn` flag. Chunks built in memory never are.
+    pub fn is_light_on(&self) -> bool {
+        let nbt = match &self.nbt {
+            Some(nbt) => nbt,
+            None => return false,
+        };
+        nbt.get_byte("isLightOn")
+            .or_else(|_| nbt.get("Level").and_then(|level| level.get_byte("isLightOn")))
+            .map_or(false, |light_on| light_on != 0)
+    }
+
+    /// Whether the light has to be computed before the chunk can be used, i.e. the chunk got past the light stage
+    /// but its light isn't marked as valid. Chunks that haven't reached that stage don't have light yet anyways.
+    pub fn needs_light_recalc(&self) -> bool {
+        self.is_at_least(ChunkStatus::Light) && !self.is_light_on()
+    }
+
+    /// Finds a compound stored by mods in the chunk NBT, either named after the namespace (like `Forge`) or the
+    /// first one with a namespaced name (like `neoforge:attachments`).
+    ///
+    /// The top level is searched first, then the `Level` compound of chunks from before 1.18.
+    pub fn mod_data(&self, namespace: &str) -> Option<&NbtTag> {
+        let root = match self.nbt.as_ref()? {
+            NbtTag::Compound(_, children) => children,
+            _ => return None,
+        };
+        Self::find_mod_compound(root, namespace).or_else(|| {
+            match Self::find_compound(root, |name| name == "Level")? {
+                NbtTag::Compound(_, level) => Self::find_mod_compound(level, namespace),
+                _ => None,
+            }
+        })
+    }
+
+    /// The stored `Heightmaps` compound, untouched so it can be sent to clients as is. Only chunks parsed from a
+    /// region file have it.
+    pub fn raw_heightmaps(&self) -> Option<&NbtTag> {
+        let root = match self.nbt.as_ref()? {
+            NbtTag::Compound(_, children) => children,
+            _ => return None,
+        };
+        Self::find_compound(root, |name| name == "Heightmaps").or_else(|| {
+            match Self::find_compound(root, |name| name == "Level")? {
+                NbtTag::Compound(_, level) => Self::find_compound(level, |name| name == "Heightmaps"),
+                _ => None,
+            }
+        })
+    }
+
+    fn find_mod_compound<'a>(tags: &'a [NbtTag], namespace: &str) -> Option<&'a NbtTag> {
+        let prefix = format!("{}:", namespace);
+        Self::find_compound(tags, |name| name == namespace)
+            .or_else(|| Self::find_compound(tags, |name| name.starts_with(&prefix)))
+    }
+
+    fn find_compound(tags: &[NbtTag], is_match: impl Fn(&str) -> bool) -> Option<&NbtTag> {
+        tags.iter().find(|tag| matches!(tag, NbtTag::Compound(name, _) if is_match(name)))
+    }
+
+    /// The positions still queued for block updates after generation, with x and z relative to the chunk and the
+    /// world y. Empty for chunks from other sources than a region file.
+    pub fn post_processing(&self) -> Vec<Position> {
+        let nbt = match &self.nbt {
+            Some(nbt) => nbt,
+            None => return vec![],
+        };
+        let lists = nbt.get_list("PostProcessing")
+            .or_else(|_| nbt.get("Level").and_then(|level| level.get_list("PostProcessing")))
+            .unwrap_or_default();
+
+        let mut positions = vec![];
+        // One list per section from the bottom up, each holding packed positions relative to the section
+        for (index, list) in lists.iter().enumerate() {
+            let section_y = self.min_section_y() as i32 + index as i32;
+            if let NbtTag::List(_, packed) = list {
+                for position in packed {
+                    if let NbtTag::Short(_, packed) = position {
+                        let packed = *packed as u16 as i32;
+                        positions.push(Position::new(packed & 15, section_y*16 + ((packed >> 4) & 15), (packed >> 8) & 15));
+                    }
+                }
+            }
+        }
+        positions
+    }
+
+    /// Returns the chunk positions of the structure starts this chunk is part of, keyed by structure type.
+    pub fn structure_references(&self) -> HashMap<String, Vec<Position>> {
+        let nbt = match &self.nbt {
+            Some(nbt) => nbt,
+            None => return HashMap::new(),
+        };
+        // Before 1.18 the compound was called "Structures" and lived inside "Level"
+        let structures = nbt.get("structures")
+            .or_else(|_| nbt.get("Level").and_then(|level| level.get("Structures")));
+        let references = match structures.and_then(|s| s.get_compound("References")) {
+            Ok(references) => references,
+            Err(_) => return HashMap::new(),
+        };
+
+        let mut structure_references = HashMap::new();
+        for reference in references {
+            if let NbtTag::LongArray(name, keys) = reference {
+                let positions = keys.iter().map(|key| Position::from_chunk_key(*key)).collect();
+                structure_references.insert(name, positions);
+            }
+        }
+        structure_references
+    }
 }
\ No newline at end of file
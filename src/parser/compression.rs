@@ -0,0 +1,54 @@
+use std::io::Read;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use inbt::NbtTag;
+use log::warn;
+use crate::{McaParseError, Result};
+
+/// Decompresses and parses the chunk payloads stored in region files.
+///
+/// Every method borrows the raw chunk data straight from the region, without the length and compression type
+/// header, and gets the most bytes the data may decompress to before failing with [`McaParseError::ChunkTooLarge`].
+pub trait Decompressor {
+    /// Compression type 1
+    fn gzip(&self, data: &[u8], max_len: usize) -> Result<NbtTag>;
+
+    /// Compression type 2, used by almost every chunk
+    fn zlib(&self, data: &[u8], max_len: usize) -> Result<NbtTag>;
+
+    /// Compression type 4, available since 24w04a
+    fn lz4(&self, _data: &[u8], _max_len: usize) -> Result<NbtTag> {
+        Err(McaParseError::UnsupportedCompression(4))
+    }
+}
+
+/// The default decompressor, using streaming decoders and the parser from `inbt`.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct InbtDecompressor;
+
+impl Decompressor for InbtDecompressor {
+    fn gzip(&self, data: &[u8], max_len: usize) -> Result<NbtTag> {
+        Ok(inbt::nbt_parser::parse_binary(read_bounded(GzDecoder::new(data), max_len)?))
+    }
+
+    /// Decodes the data in one pass, stopping at the end of the zlib stream so garbage some tools leave behind it
+    /// in the sectors is ignored.
+    fn zlib(&self, data: &[u8], max_len: usize) -> Result<NbtTag> {
+        let mut decoder = ZlibDecoder::new(data);
+        let nbt = read_bounded(&mut decoder, max_len)?;
+        let consumed = decoder.total_in() as usize;
+        if consumed < data.len() {
+            warn!("Ignoring {} trailing bytes after the zlib stream", data.len() - consumed);
+        }
+        Ok(inbt::nbt_parser::parse_binary(nbt))
+    }
+}
+
+/// Reads all of `reader`, stopping as soon as it gives more than `max_len` bytes
+fn read_bounded(reader: impl Read, max_len: usize) -> Result<Vec<u8>> {
+    let mut data = vec![];
+    reader.take(max_len as u64 + 1).read_to_end(&mut data).map_err(McaParseError::DecompressionError)?;
+    if data.len() > max_len {
+        return Err(McaParseError::ChunkTooLarge(max_len));
+    }
+    Ok(data)
+}
@@ -0,0 +1,114 @@
+//! Numeric block ids from before the 1.13 flattening, enabled by the `legacy` feature.
+
+use std::collections::BTreeMap;
+use crate::Block;
+
+/// Colors in the order of their data values
+const COLORS: [&str; 16] = [
+    "white", "orange", "magenta", "light_blue", "yellow", "lime", "pink", "gray", "light_gray", "cyan", "purple",
+    "blue", "brown", "green", "red", "black",
+];
+
+/// Wood types in the order of their data values
+const WOODS: [&str; 6] = ["oak", "spruce", "birch", "jungle", "acacia", "dark_oak"];
+
+/// Block names by numeric id as of 1.12, using the names they got in 1.13. Ids where the data value picks the
+/// block are handled in [`block_name`].
+const BLOCK_NAMES: [&str; 256] = [
+    "air", "stone", "grass_block", "dirt", "cobblestone", "oak_planks", "oak_sapling", "bedrock", "water", "water",
+    "lava", "lava", "sand", "gravel", "gold_ore", "iron_ore", "coal_ore", "oak_log", "oak_leaves", "sponge",
+    "glass", "lapis_ore", "lapis_block", "dispenser", "sandstone", "note_block", "red_bed", "powered_rail",
+    "detector_rail", "sticky_piston", "cobweb", "grass", "dead_bush", "piston", "piston_head", "white_wool",
+    "moving_piston", "dandelion", "poppy", "brown_mushroom", "red_mushroom", "gold_block", "iron_block",
+    "stone_slab", "stone_slab", "bricks", "tnt", "bookshelf", "mossy_cobblestone", "obsidian", "torch", "fire",
+    "spawner", "oak_stairs", "chest", "redstone_wire", "diamond_ore", "diamond_block", "crafting_table", "wheat",
+    "farmland", "furnace", "furnace", "sign", "oak_door", "ladder", "rail", "cobblestone_stairs", "wall_sign",
+    "lever", "stone_pressure_plate", "iron_door", "oak_pressure_plate", "redstone_ore", "redstone_ore",
+    "redstone_torch", "redstone_torch", "stone_button", "snow", "ice", "snow_block", "cactus", "clay",
+    "sugar_cane", "jukebox", "oak_fence", "carved_pumpkin", "netherrack", "soul_sand", "glowstone",
+    "nether_portal", "jack_o_lantern", "cake", "repeater", "repeater", "white_stained_glass", "oak_trapdoor",
+    "infested_stone", "stone_bricks", "brown_mushroom_block", "red_mushroom_block", "iron_bars", "glass_pane",
+    "melon", "pumpkin_stem", "melon_stem", "vine", "oak_fence_gate", "brick_stairs", "stone_brick_stairs",
+    "mycelium", "lily_pad", "nether_bricks", "nether_brick_fence", "nether_brick_stairs", "nether_wart",
+    "enchanting_table", "brewing_stand", "cauldron", "end_portal", "end_portal_frame", "end_stone", "dragon_egg",
+    "redstone_lamp", "redstone_lamp", "oak_slab", "oak_slab", "cocoa", "sandstone_stairs", "emerald_ore",
+    "ender_chest", "tripwire_hook", "tripwire", "emerald_block", "spruce_stairs", "birch_stairs", "jungle_stairs",
+    "command_block", "beacon", "cobblestone_wall", "flower_pot", "carrots", "potatoes", "oak_button",
+    "skeleton_skull", "anvil", "trapped_chest", "light_weighted_pressure_plate", "heavy_weighted_pressure_plate",
+    "comparator", "comparator", "daylight_detector", "redstone_block", "nether_quartz_ore", "hopper",
+    "quartz_block", "quartz_stairs", "activator_rail", "dropper", "white_terracotta", "white_stained_glass_pane",
+    "acacia_leaves", "acacia_log", "acacia_stairs", "dark_oak_stairs", "slime_block", "barrier", "iron_trapdoor",
+    "prismarine", "sea_lantern", "hay_block", "white_carpet", "terracotta", "coal_block", "packed_ice",
+    "sunflower", "white_banner", "white_wall_banner", "daylight_detector", "red_sandstone",
+    "red_sandstone_stairs", "red_sandstone_slab", "red_sandstone_slab", "spruce_fence_gate", "birch_fence_gate",
+    "jungle_fence_gate", "dark_oak_fence_gate", "acacia_fence_gate", "spruce_fence", "birch_fence",
+    "jungle_fence", "dark_oak_fence", "acacia_fence", "spruce_door", "birch_door", "jungle_door", "acacia_door",
+    "dark_oak_door", "end_rod", "chorus_plant", "chorus_flower", "purpur_block", "purpur_pillar",
+    "purpur_stairs", "purpur_slab", "purpur_slab", "end_stone_bricks", "beetroots", "grass_path",
+    "end_gateway", "repeating_command_block", "chain_command_block", "frosted_ice", "magma_block",
+    "nether_wart_block", "red_nether_bricks", "bone_block", "structure_void", "observer", "white_shulker_box",
+    "orange_shulker_box", "magenta_shulker_box", "light_blue_shulker_box", "yellow_shulker_box",
+    "lime_shulker_box", "pink_shulker_box", "gray_shulker_box", "light_gray_shulker_box", "cyan_shulker_box",
+    "purple_shulker_box", "blue_shulker_box", "brown_shulker_box", "green_shulker_box", "red_shulker_box",
+    "black_shulker_box", "white_glazed_terracotta", "orange_glazed_terracotta", "magenta_glazed_terracotta",
+    "light_blue_glazed_terracotta", "yellow_glazed_terracotta", "lime_glazed_terracotta",
+    "pink_glazed_terracotta", "gray_glazed_terracotta", "light_gray_glazed_terracotta", "cyan_glazed_terracotta",
+    "purple_glazed_terracotta", "blue_glazed_terracotta", "brown_glazed_terracotta", "green_glazed_terracotta",
+    "red_glazed_terracotta", "black_glazed_terracotta", "white_concrete", "white_concrete_powder", "", "",
+    "structure_block",
+];
+
+/// The 1.13 name of a legacy block, using the data value for the blocks that have variants.
+fn block_name(id: u8, data: u8) -> Option<String> {
+    let variant = |names: &[&str]| names.get(data as usize).map(|name| name.to_string());
+    let name = match id {
+        1 => return variant(&["stone", "granite", "polished_granite", "diorite", "polished_diorite", "andesite", "polished_andesite"]),
+        3 => return variant(&["dirt", "coarse_dirt", "podzol"]),
+        5 => format!("{}_planks", WOODS.get(data as usize)?),
+        6 => format!("{}_sapling", WOODS.get((data & 7) as usize)?),
+        12 => return variant(&["sand", "red_sand"]),
+        17 => format!("{}_log", WOODS[(data & 3) as usize]),
+        18 => format!("{}_leaves", WOODS[(data & 3) as usize]),
+        19 => return variant(&["sponge", "wet_sponge"]),
+        24 => return variant(&["sandstone", "chiseled_sandstone", "cut_sandstone"]),
+        31 => return variant(&["dead_bush", "grass", "fern"]),
+        35 => format!("{}_wool", COLORS[data as usize]),
+        38 => return variant(&["poppy", "blue_orchid", "allium", "azure_bluet", "red_tulip", "orange_tulip", "white_tulip", "pink_tulip", "oxeye_daisy"]),
+        43 | 44 => ["stone_slab", "sandstone_slab", "petrified_oak_slab", "cobblestone_slab", "brick_slab", "stone_brick_slab", "nether_brick_slab", "quartz_slab"][(data & 7) as usize].to_string(),
+        95 => format!("{}_stained_glass", COLORS[data as usize]),
+        97 => return variant(&["infested_stone", "infested_cobblestone", "infested_stone_bricks", "infested_mossy_stone_bricks", "infested_cracked_stone_bricks", "infested_chiseled_stone_bricks"]),
+        98 => return variant(&["stone_bricks", "mossy_stone_bricks", "cracked_stone_bricks", "chiseled_stone_bricks"]),
+        125 | 126 => format!("{}_slab", WOODS.get((data & 7) as usize)?),
+        139 => return variant(&["cobblestone_wall", "mossy_cobblestone_wall"]),
+        145 => ["anvil", "chipped_anvil", "damaged_anvil"].get((data >> 2) as usize)?.to_string(),
+        155 => return variant(&["quartz_block", "chiseled_quartz_block", "quartz_pillar", "quartz_pillar", "quartz_pillar"]),
+        159 => format!("{}_terracotta", COLORS[data as usize]),
+        160 => format!("{}_stained_glass_pane", COLORS[data as usize]),
+        161 => format!("{}_leaves", WOODS.get(4 + (data & 1) as usize)?),
+        162 => format!("{}_log", WOODS.get(4 + (data & 1) as usize)?),
+        168 => return variant(&["prismarine", "prismarine_bricks", "dark_prismarine"]),
+        171 => format!("{}_carpet", COLORS[data as usize]),
+        175 => ["sunflower", "lilac", "tall_grass", "large_fern", "rose_bush", "peony"].get((data & 7) as usize)?.to_string(),
+        179 => return variant(&["red_sandstone", "chiseled_red_sandstone", "cut_red_sandstone"]),
+        251 => format!("{}_concrete", COLORS[data as usize]),
+        252 => format!("{}_concrete_powder", COLORS[data as usize]),
+        _ => BLOCK_NAMES[id as usize].to_string(),
+    };
+    Some(name)
+}
+
+/// Converts a numeric block id and data value to a block, or `None` for unknown ids and data values.
+///
+/// Only the block itself is converted, the properties stored in the data value (facing, age, ...) aren't, and blocks
+/// get the names they had in 1.13. Ids above 255 (from the `Add` array) are only used by mods and always unknown.
+pub(crate) fn legacy_block(id: u16, data: u8) -> Option<Block> {
+    let id = u8::try_from(id).ok()?;
+    let name = block_name(id, data & 15)?;
+    if name.is_empty() {
+        return None;
+    }
+    Some(Block {
+        identifier: format!("minecraft:{}", name),
+        properties: BTreeMap::new(),
+    })
+}
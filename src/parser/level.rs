@@ -1,5 +1,5 @@
 use inbt::NbtTag;
-use crate::McaParseError;
+use crate::{Position, Result};
 
 #[derive(Debug)]
 pub struct Level {
@@ -7,8 +7,51 @@ pub struct Level {
 }
 
 impl Level {
-    pub fn parse_level(level_data: Vec<u8>) -> Result<Self, McaParseError> {
+    pub fn parse_level(level_data: Vec<u8>) -> Result<Self> {
         let nbt = inbt::nbt_parser::parse_gzip(level_data)?;
         Ok(Self { nbt })
     }
-}
\ No newline at end of file
+
+    /// The world spawn point
+    pub fn spawn(&self) -> Option<Position> {
+        let data = self.nbt.get("Data").ok()?;
+        Some(Position::new(data.get_int("SpawnX").ok()?, data.get_int("SpawnY").ok()?, data.get_int("SpawnZ").ok()?))
+    }
+
+    /// The default game mode, 0 survival, 1 creative, 2 adventure and 3 spectator
+    pub fn game_type(&self) -> Option<i32> {
+        self.nbt.get("Data").ok()?.get_int("GameType").ok()
+    }
+
+    pub fn is_hardcore(&self) -> bool {
+        self.flag("hardcore")
+    }
+
+    /// Whether cheats are enabled
+    pub fn allow_commands(&self) -> bool {
+        self.flag("allowCommands")
+    }
+
+    /// The difficulty, 0 peaceful, 1 easy, 2 normal and 3 hard
+    pub fn difficulty(&self) -> Option<u8> {
+        self.nbt.get("Data").ok()?.get_byte("Difficulty").ok().map(|difficulty| difficulty as u8)
+    }
+
+    pub fn is_raining(&self) -> bool {
+        self.flag("raining")
+    }
+
+    pub fn is_thundering(&self) -> bool {
+        self.flag("thundering")
+    }
+
+    /// Ticks since the world was created as shown by the daylight cycle, the time of day is this modulo 24000
+    pub fn day_time(&self) -> i64 {
+        self.nbt.get("Data").and_then(|data| data.get_long("DayTime")).unwrap_or(0)
+    }
+
+    /// Reads a byte flag in `Data`, missing flags are false
+    fn flag(&self, name: &str) -> bool {
+        self.nbt.get("Data").and_then(|data| data.get_byte(name)).map_or(false, |flag| flag != 0)
+    }
+}
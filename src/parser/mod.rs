@@ -5,11 +5,22 @@ pub mod chunk;
 pub mod section;
 pub mod level;
 pub mod parse_error;
+pub mod compression;
+mod nbt_writer;
+pub mod schematic;
+#[cfg(feature = "block_tables")]
+mod block_tables;
+#[cfg(feature = "legacy")]
+mod legacy;
+#[cfg(feature = "testutil")]
+pub mod testutil;
 
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 use inbt::{NbtParseError, NbtTag};
+use crate::McaParseError;
 
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
 pub struct Position {
@@ -49,11 +60,15 @@ impl Position {
         Self::new(self.x>>9, 0, self.z>>9)
     }
 
+    /// Name of the region file containing the block position, e.g. `r.-1.0.mca` for x -1 and z 0
+    pub fn region_filename(&self) -> String {
+        let region = self.region_in_world();
+        format!("r.{}.{}.mca", region.x, region.z)
+    }
+
     pub fn chunk_in_region(&self) -> Self {
-        // If negative then we subtract an extra 1
-        let x = self.x/16 - (self.x < 0) as i32;
-        let z = self.z/16 - (self.z < 0) as i32;
-        Self::new(x, 0, z)
+        // Shifting rounds towards negative infinity, so -16..=-1 is chunk -1
+        Self::new(self.x>>4, 0, self.z>>4)
     }
 
     pub fn section_index_in_chunk(&self) -> Option<u32> {
@@ -66,6 +81,16 @@ impl Position {
         Some(section as u32)
     }
 
+    /// Returns the position with y clamped to `min_y..=max_y`, e.g. the build limits of a dimension.
+    pub fn clamp_y(&self, min_y: i32, max_y: i32) -> Position {
+        Self::new(self.x, self.y.clamp(min_y, max_y), self.z)
+    }
+
+    /// Whether y is inside of `min_y..=max_y`
+    pub fn is_valid_y(&self, min_y: i32, max_y: i32) -> bool {
+        (min_y..=max_y).contains(&self.y)
+    }
+
     pub fn block_in_section(&self) -> Self {
         let x = self.x.rem_euclid(16);
         let y = self.y.rem_euclid(16);
@@ -73,6 +98,38 @@ impl Position {
         Self::new(x, y, z)
     }
 
+    /// Packs chunk coordinates into one long the way Minecraft does, used e.g. by structure references.
+    ///
+    /// x goes in the low 32 bits and z in the high 32 bits, the y coordinate is dropped.
+    pub fn to_chunk_key(&self) -> i64 {
+        (self.x as u32 as i64) | ((self.z as u32 as i64) << 32)
+    }
+
+    /// The inverse of [`Position::to_chunk_key`], with y set to 0.
+    pub fn from_chunk_key(key: i64) -> Position {
+        Self::new(key as i32, 0, (key >> 32) as i32)
+    }
+
+    /// Euclidean distance between two positions
+    pub fn distance(&self, other: &Position) -> f64 {
+        let dx = (self.x as i64 - other.x as i64) as f64;
+        let dy = (self.y as i64 - other.y as i64) as f64;
+        let dz = (self.z as i64 - other.z as i64) as f64;
+        (dx*dx + dy*dy + dz*dz).sqrt()
+    }
+
+    /// Sum of the distances along each axis, widened to avoid overflow for coordinates far apart
+    pub fn manhattan_distance(&self, other: &Position) -> i64 {
+        (self.x as i64 - other.x as i64).abs()
+            + (self.y as i64 - other.y as i64).abs()
+            + (self.z as i64 - other.z as i64).abs()
+    }
+
+    /// The 256 world x and z coordinates of the columns in a chunk, with x changing fastest.
+    pub fn chunk_columns(chunk: Position) -> impl Iterator<Item = (i32, i32)> {
+        (0..256).map(move |i| (chunk.x*16 + i%16, chunk.z*16 + i/16))
+    }
+
     pub fn block_index_in_section(&self) -> usize {
         let pos = self.block_in_section();
         let block_pos = pos.y*16*16 + pos.z*16 + pos.x;
@@ -86,6 +143,44 @@ impl Display for Position {
     }
 }
 
+/// An axis aligned box of positions, with both corners inclusive.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BoundingBox {
+    pub min: Position,
+    pub max: Position,
+}
+
+impl BoundingBox {
+    /// Creates a box spanning the two corners, which can be given in any order.
+    pub fn new(a: Position, b: Position) -> Self {
+        Self {
+            min: Position::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Position::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    pub fn contains(&self, pos: &Position) -> bool {
+        (self.min.x..=self.max.x).contains(&pos.x)
+            && (self.min.y..=self.max.y).contains(&pos.y)
+            && (self.min.z..=self.max.z).contains(&pos.z)
+    }
+
+    /// Returns the overlapping part of two boxes, if any.
+    pub fn intersection(&self, other: &BoundingBox) -> Option<BoundingBox> {
+        let min = Position::new(self.min.x.max(other.min.x), self.min.y.max(other.min.y), self.min.z.max(other.min.z));
+        let max = Position::new(self.max.x.min(other.max.x), self.max.y.min(other.max.y), self.max.z.min(other.max.z));
+        if min.x > max.x || min.y > max.y || min.z > max.z {
+            return None;
+        }
+        Some(Self { min, max })
+    }
+
+    /// Number of positions along each axis
+    pub fn size(&self) -> Position {
+        Position::new(self.max.x - self.min.x + 1, self.max.y - self.min.y + 1, self.max.z - self.min.z + 1)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Block {
     identifier: String,
@@ -119,6 +214,106 @@ impl Block {
         }
     }
 
+    /// Returns the block state as it's stored in palettes, with `Name` and optionally `Properties`.
+    pub fn to_nbt(&self) -> NbtTag {
+        let mut tags = vec![NbtTag::String("Name".to_string(), self.identifier.clone())];
+        if !self.properties.is_empty() {
+            let properties = self.properties.iter()
+                .map(|(name, value)| NbtTag::String(name.clone(), value.clone()))
+                .collect();
+            tags.push(NbtTag::Compound("Properties".to_string(), properties));
+        }
+        NbtTag::Compound(String::new(), tags)
+    }
+
+    /// Returns a copy of the block with the property set, e.g. `block.with_property("waterlogged", "true")`
+    pub fn with_property(&self, key: &str, value: &str) -> Block {
+        let mut block = self.clone();
+        block.properties.insert(key.to_string(), value.to_string());
+        block
+    }
+
+    /// Returns a copy of the block without the properties that are set to their vanilla default, so blocks that
+    /// only differ in whether defaults were written compare equal.
+    ///
+    /// Uses the bundled table, which doesn't know every property. Properties missing from it are kept.
+    #[cfg(feature = "block_tables")]
+    pub fn normalized(&self) -> Block {
+        self.normalized_by(|identifier, property| block_tables::default_property(identifier, property))
+    }
+
+    /// Same as [`Block::normalized`], but with defaults from `defaults`, keyed by block identifier and then property.
+    pub fn normalized_with(&self, defaults: &BTreeMap<String, BTreeMap<String, String>>) -> Block {
+        self.normalized_by(|identifier, property| defaults.get(identifier)?.get(property).map(|v| v.as_str()))
+    }
+
+    fn normalized_by<'a>(&self, default_of: impl Fn(&str, &str) -> Option<&'a str>) -> Block {
+        let properties = self.properties.iter()
+            .filter(|(name, value)| default_of(&self.identifier, name) != Some(value.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        Block {
+            identifier: self.identifier.clone(),
+            properties,
+        }
+    }
+
+    /// Returns a copy of the block with the identifier it has in the version with `target_data_version`, e.g.
+    /// `minecraft:grass_path` becomes `minecraft:dirt_path` since 1.17. Blocks that weren't renamed are unchanged.
+    ///
+    /// Backed by a bundled table of vanilla renames, property changes aren't migrated.
+    #[cfg(feature = "block_tables")]
+    pub fn migrate_to(&self, target_data_version: i32) -> Block {
+        match block_tables::migrated_identifier(&self.identifier, target_data_version) {
+            Some(identifier) => Block {
+                identifier,
+                properties: self.properties.clone(),
+            },
+            None => self.clone(),
+        }
+    }
+
+    /// Checks the identifier, e.g. `block.is("minecraft:diamond_ore")`
+    pub fn is(&self, identifier: &str) -> bool {
+        self.identifier == identifier
+    }
+
+    /// Whether the block is a full cube hiding the faces of its neighbors, i.e. stone but not glass or slabs.
+    ///
+    /// Backed by a bundled table of vanilla blocks, blocks from other namespaces are never opaque.
+    #[cfg(feature = "block_tables")]
+    pub fn is_opaque_full_cube(&self) -> bool {
+        block_tables::is_opaque_full_cube(&self.identifier)
+    }
+
+    /// Whether the block has a block entity holding extra data, like chests, signs and furnaces.
+    ///
+    /// Backed by a bundled table of vanilla blocks, see [`Block::has_block_entity_or`] for modded blocks.
+    #[cfg(feature = "block_tables")]
+    pub fn has_block_entity(&self) -> bool {
+        block_tables::has_block_entity(&self.identifier)
+    }
+
+    /// Same as [`Block::has_block_entity`], but also true for blocks matching `is_block_entity`.
+    #[cfg(feature = "block_tables")]
+    pub fn has_block_entity_or(&self, is_block_entity: impl Fn(&Block) -> bool) -> bool {
+        self.has_block_entity() || is_block_entity(self)
+    }
+
+    /// Returns true for all the air variants: air, cave air and void air.
+    pub fn is_air(&self) -> bool {
+        matches!(self.identifier.as_str(), "minecraft:air" | "minecraft:cave_air" | "minecraft:void_air")
+    }
+
+    /// Number of block state properties, e.g. 0 for stone
+    pub fn properties_len(&self) -> usize {
+        self.properties.len()
+    }
+
+    pub fn has_properties(&self) -> bool {
+        !self.properties.is_empty()
+    }
+
     pub fn identifier(&self) -> &String {
         &self.identifier
     }
@@ -126,21 +321,479 @@ impl Block {
     pub fn properties(&self) -> &BTreeMap<String, String> {
         &self.properties
     }
+
+    /// The properties as name and value pairs, sorted by name
+    pub fn properties_sorted(&self) -> Vec<(&str, &str)> {
+        self.properties.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect()
+    }
+}
+
+impl FromStr for Block {
+    type Err = McaParseError;
+
+    /// Parses a block state string like `minecraft:oak_stairs[facing=north,half=bottom]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || McaParseError::InvalidBlockState(s.to_string());
+        let (identifier, properties) = match s.split_once('[') {
+            Some((identifier, properties)) => (identifier, properties.strip_suffix(']').ok_or_else(invalid)?),
+            None => (s, ""),
+        };
+        if identifier.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut property_map = BTreeMap::new();
+        for property in properties.split(',').filter(|p| !p.is_empty()) {
+            let (name, value) = property.split_once('=').ok_or_else(invalid)?;
+            property_map.insert(name.to_string(), value.to_string());
+        }
+        Ok(Self {
+            identifier: identifier.to_string(),
+            properties: property_map,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
+    use std::collections::{BTreeMap, BTreeSet};
     use std::fs;
+    use std::io;
     use std::path::PathBuf;
-    use crate::parser::region::Region;
-    use crate::{Block, Position, World};
+    use std::sync::{Arc, Mutex};
+    use inbt::NbtTag;
+    use crate::parser::chunk::{Chunk, ChunkStatus};
+    use crate::parser::compression::InbtDecompressor;
+    use crate::parser::nbt_writer;
+    use crate::parser::region::{ParseOptions, Region};
+    use crate::parser::section::Section;
+    use crate::{Block, BlockQueryError, McaParseError, Position, RegionSource, World};
 
     #[test]
     fn position_conversion() {
         assert_eq!(Position { x: 0, y: 0, z: 0 }.region_in_world(), Position::new(0, 0, 0));
         assert_eq!(Position { x: -512, y: 0, z: 0 }.region_in_world(), Position::new(-1, 0, 0));
         assert_eq!(Position { x: -1, y: 0, z: 0 }.region_in_world(), Position::new(-1, 0, 0));
+        assert_eq!(Position::new(-1, 0, -16).chunk_in_region(), Position::new(-1, 0, -1));
+        assert_eq!(Position::new(-17, 0, 15).chunk_in_region(), Position::new(-2, 0, 0));
+    }
+
+    #[test]
+    fn negative_chunk_slots() {
+        let mut region = Region::new();
+        let chunk = Chunk::new_empty(Position::new(-17, 0, -1), -4, 19, 3700);
+        assert!(region.insert_chunk(Position::new(-1, 0, 0), chunk.clone()).is_err());
+        region.insert_chunk(Position::new(15, 0, 31), chunk.clone()).unwrap();
+        region.insert_chunk(Position::new(15, 0, 31), chunk).unwrap();
+        assert_eq!(region.chunk_count(), 1);
+        assert!(region.get_chunk(Position::new(-17, 0, -1)).is_some());
+        assert!(region.get_chunk(Position::new(15, 0, 31)).is_none());
+        assert_eq!(region.get_identifier(Position::new(-17*16 + 3, 0, -5)), Some("minecraft:air"));
+    }
+
+    #[test]
+    fn write_edited_chunks() {
+        let test_data = fs::read(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_files/r.0.0.mca")).expect("Failed to open test file");
+        let options = ParseOptions { retain_raw_chunks: true, ..ParseOptions::default() };
+        let mut region = Region::parse_region_with_options(test_data, &options).unwrap();
+        let edited = *region.chunks()[0].chunk_pos();
+        let untouched = *region.chunks()[1].chunk_pos();
+        let pos = Position::new(edited.x*16 + 3, 70, edited.z*16 + 5);
+        let gold = "minecraft:gold_block".parse::<Block>().unwrap();
+        assert!(region.get_chunk_mut(edited).unwrap().set(pos, gold.clone()));
+        assert!(region.raw_chunk_bytes(edited.x, edited.z).is_none());
+
+        let mut written = vec![];
+        region.write_to(&mut written).unwrap();
+        let reparsed = Region::parse_region_with_options(written, &options).unwrap();
+        assert_eq!(reparsed.get(pos), Some(gold));
+        assert_eq!(reparsed.chunks().len(), region.chunks().len());
+        assert_eq!(reparsed.raw_chunk_bytes(untouched.x, untouched.z), region.raw_chunk_bytes(untouched.x, untouched.z));
+    }
+
+    #[test]
+    fn nbt_length_limits() {
+        let string = |value: String| NbtTag::Compound(String::new(), vec![NbtTag::String("value".to_string(), value)]);
+        assert!(nbt_writer::write_nbt(&string("a".repeat(65535))).is_ok());
+        assert!(matches!(nbt_writer::write_nbt(&string("a".repeat(65536))), Err(McaParseError::NbtEncodeError(_))));
+        // NUL takes two bytes in modified UTF-8
+        assert!(nbt_writer::write_nbt(&string("\0".repeat(40000))).is_err());
+    }
+
+    #[test]
+    fn chunk_nbt_layouts() {
+        // More blocks than 4 bits can index, so the spanning layout has entries crossing long boundaries
+        let blocks = (0..20).map(|i| format!("test:block_{}", i).parse::<Block>().unwrap()).collect::<Vec<Block>>();
+        let pos = |i: usize| Position::new(16 + i as i32%16, 16 + i as i32/16, 32);
+        for (data_version, level) in [(3700, false), (2580, true), (2230, true)] {
+            let mut chunk = Chunk::new_empty(Position::new(1, 0, 2), 0, 1, data_version);
+            for (i, block) in blocks.iter().enumerate() {
+                chunk.set(pos(i), block.clone());
+            }
+            assert_eq!(chunk.to_nbt().unwrap().get("Level").is_ok(), level);
+
+            let mut region = Region::new();
+            region.insert_chunk(Position::new(1, 0, 2), chunk).unwrap();
+            let mut written = vec![];
+            region.write_to(&mut written).unwrap();
+            let reparsed = Region::parse_region(written).unwrap();
+            assert_eq!(reparsed.chunks()[0].nbt().unwrap().get("Level").is_ok(), level);
+            for (i, block) in blocks.iter().enumerate() {
+                assert_eq!(reparsed.get(pos(i)).as_ref(), Some(block));
+            }
+            assert_eq!(reparsed.get_identifier(Position::new(16, 0, 32)), Some("minecraft:air"));
+        }
+        assert!(Chunk::new_empty(Position::new(0, 0, 0), 0, 15, 1343).to_nbt().is_err());
+    }
+
+    /// Makes the bytes of a region holding only `chunk`, in the slot of the given local chunk position
+    fn region_with_chunk(local_x: usize, local_z: usize, chunk: &NbtTag) -> Vec<u8> {
+        region_with_payload(local_x, local_z, &nbt_writer::write_chunk_payload(chunk).unwrap())
+    }
+
+    fn region_with_payload(local_x: usize, local_z: usize, payload: &[u8]) -> Vec<u8> {
+        let slot = local_x + local_z*32;
+        let mut region = vec![0; 8192];
+        region[slot*4 + 2] = 2;
+        region[slot*4 + 3] = payload.len().div_ceil(4096) as u8;
+        region.extend_from_slice(payload);
+        region
+    }
+
+    /// A generated 1.20 chunk at chunk position 0, 0 with a single section of stone
+    fn stone_chunk() -> NbtTag {
+        let mut section = Section::empty(0);
+        section.fill("minecraft:stone".parse().unwrap());
+        chunk_with_sections(vec![section.to_nbt()])
+    }
+
+    /// A generated 1.20 chunk at chunk position 0, 0 starting at section 0
+    fn chunk_with_sections(sections: Vec<NbtTag>) -> NbtTag {
+        NbtTag::Compound(String::new(), vec![
+            NbtTag::Int("DataVersion".to_string(), 3700),
+            NbtTag::Int("xPos".to_string(), 0),
+            NbtTag::Int("yPos".to_string(), 0),
+            NbtTag::Int("zPos".to_string(), 0),
+            NbtTag::String("Status".to_string(), "minecraft:full".to_string()),
+            NbtTag::List("sections".to_string(), sections),
+        ])
+    }
+
+    #[test]
+    fn kept_nbt_without_parsed_sections() {
+        let section = |y: i8, block: Option<&str>| {
+            let mut tags = vec![NbtTag::Byte("Y".to_string(), y), NbtTag::ByteArray("SkyLight".to_string(), vec![0x77; 2048])];
+            if let Some(block) = block {
+                let palette = vec![NbtTag::Compound(String::new(), vec![NbtTag::String("Name".to_string(), block.to_string())])];
+                tags.push(NbtTag::Compound("block_states".to_string(), vec![NbtTag::List("palette".to_string(), palette)]));
+            }
+            NbtTag::Compound(String::new(), tags)
+        };
+        let chunk = chunk_with_sections(vec![section(0, Some("minecraft:stone")), section(1, Some("minecraft:air")), section(2, None)]);
+        let options = ParseOptions { drop_empty_sections: true, ..ParseOptions::default() };
+        let region = Region::parse_region_with_options(region_with_chunk(0, 0, &chunk), &options).unwrap();
+        // The stone section is only kept parsed, the dropped and light-only ones only in the NBT
+        assert_eq!(region.chunks()[0].section_count(), 1);
+        assert_eq!(region.chunks()[0].nbt().unwrap().get_list("sections").unwrap().len(), 2);
+
+        let mut written = vec![];
+        region.write_to(&mut written).unwrap();
+        let reparsed = Region::parse_region(written).unwrap();
+        let chunk = &reparsed.chunks()[0];
+        assert_eq!(chunk.section_count(), 2);
+        assert_eq!(chunk.nbt().unwrap().get_list("sections").unwrap().len(), 1);
+        assert_eq!(reparsed.get_identifier(Position::new(3, 5, 3)), Some("minecraft:stone"));
+        assert_eq!(chunk.get_sky_light(Position::new(3, 5, 3)), Some(7));
+        assert_eq!(chunk.get_sky_light(Position::new(3, 20, 3)), Some(7));
+    }
+
+    #[test]
+    fn level_chunk_sections() {
+        // 32 entries take 5 bits, so before 20w17a the 13th index is split between the first two longs
+        let palette = (0..32).map(|i| {
+            let name = if i == 0 { "minecraft:air".to_string() } else { format!("test:block_{}", i) };
+            NbtTag::Compound(String::new(), vec![NbtTag::String("Name".to_string(), name)])
+        }).collect::<Vec<NbtTag>>();
+        let mut block_states = vec![0; 320];
+        block_states[0] = (0b1011u64<<60 | 3<<5) as i64;
+        block_states[1] = 1;
+        let level_chunk = |data_version: i32| NbtTag::Compound(String::new(), vec![
+            NbtTag::Int("DataVersion".to_string(), data_version),
+            NbtTag::Compound("Level".to_string(), vec![
+                NbtTag::Int("xPos".to_string(), 1),
+                NbtTag::Int("zPos".to_string(), 2),
+                NbtTag::String("Status".to_string(), "full".to_string()),
+                NbtTag::List("Sections".to_string(), vec![
+                    NbtTag::Compound(String::new(), vec![NbtTag::Byte("Y".to_string(), -1), NbtTag::ByteArray("SkyLight".to_string(), vec![0; 2048])]),
+                    NbtTag::Compound(String::new(), vec![
+                        NbtTag::Byte("Y".to_string(), 0),
+                        NbtTag::List("Palette".to_string(), palette.clone()),
+                        NbtTag::LongArray("BlockStates".to_string(), block_states.clone()),
+                    ]),
+                ]),
+            ]),
+        ]);
+
+        let spanning = Region::parse_region(region_with_chunk(1, 2, &level_chunk(2230))).unwrap();
+        let chunk = spanning.get_chunk(Position::new(1, 0, 2)).unwrap();
+        assert_eq!((chunk.data_version(), chunk.min_section_y(), chunk.section_count()), (2230, 0, 1));
+        assert_eq!(spanning.get_identifier(Position::new(17, 0, 32)), Some("test:block_3"));
+        assert_eq!(spanning.get_identifier(Position::new(28, 0, 32)), Some("test:block_27"));
+        assert_eq!(spanning.get_identifier(Position::new(29, 0, 32)), Some("minecraft:air"));
+
+        // Since 20w17a the 13th index starts the second long instead
+        let non_spanning = Region::parse_region(region_with_chunk(1, 2, &level_chunk(2580))).unwrap();
+        assert_eq!(non_spanning.get_identifier(Position::new(17, 0, 32)), Some("test:block_3"));
+        assert_eq!(non_spanning.get_identifier(Position::new(28, 0, 32)), Some("test:block_1"));
+    }
+
+    #[test]
+    fn zlib_trailing_garbage() {
+        let mut payload = nbt_writer::write_chunk_payload(&stone_chunk()).unwrap();
+        payload.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef, 0x78, 0x9c]);
+        let length = payload.len() as u32 - 4;
+        payload[0..4].copy_from_slice(&length.to_be_bytes());
+        let region = Region::parse_region(region_with_payload(0, 0, &payload)).unwrap();
+        assert_eq!(region.get_identifier(Position::new(3, 7, 5)), Some("minecraft:stone"));
+    }
+
+    #[test]
+    fn decompressed_size_limit() {
+        let mut chunk = stone_chunk();
+        if let NbtTag::Compound(_, children) = &mut chunk {
+            children.push(NbtTag::LongArray("padding".to_string(), vec![0; 1<<18]));
+        }
+        // 2MiB of zeros compress to a few KiB
+        let payload = nbt_writer::write_chunk_payload(&chunk).unwrap();
+        assert!(payload.len() < 16*1024);
+        let region = region_with_payload(0, 0, &payload);
+        let options = ParseOptions { max_chunk_bytes: 1024*1024, ..ParseOptions::default() };
+        assert!(matches!(Region::parse_region_with_options(region.clone(), &options), Err(McaParseError::ChunkTooLarge(_))));
+        assert_eq!(Region::parse_region(region).unwrap().chunks().len(), 1);
+    }
+
+    #[test]
+    fn stream_matches_region() {
+        let mut data = fs::read(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_files/r.0.0.mca")).expect("Failed to open test file");
+        // Point an empty slot at the sectors of the first chunk, so two chunks overlap
+        let first = (0..1024).find(|slot| data[slot*4..slot*4 + 4] != [0; 4]).unwrap();
+        let empty = (0..1024).rev().find(|slot| data[slot*4..slot*4 + 4] == [0; 4]).unwrap();
+        data.copy_within(first*4..first*4 + 4, empty*4);
+
+        let options = ParseOptions { retain_raw_chunks: true, ..ParseOptions::default() };
+        let streamed = Region::parse_stream_with_options(data.as_slice(), &InbtDecompressor, &options).unwrap();
+        let parsed = Region::parse_region_with_options(data, &options).unwrap();
+        let positions = |region: &Region| region.chunks().iter().map(|chunk| *chunk.chunk_pos()).collect::<Vec<Position>>();
+        assert_eq!(positions(&streamed), positions(&parsed));
+        for (streamed, parsed) in streamed.chunks().iter().zip(parsed.chunks()) {
+            assert_eq!(streamed.distinct_blocks(), parsed.distinct_blocks());
+            assert_eq!(streamed.block_count(), parsed.block_count());
+        }
+        for slot in 0..1024 {
+            assert_eq!(streamed.raw_chunk_bytes(slot%32, slot/32), parsed.raw_chunk_bytes(slot%32, slot/32));
+        }
+        let (first, empty) = (first as i32, empty as i32);
+        assert_eq!(streamed.raw_chunk_bytes(empty%32, empty/32), parsed.raw_chunk_bytes(first%32, first/32));
+    }
+
+    /// Keeps region files in memory, so saving can be tested without touching the test files
+    #[derive(Debug)]
+    struct MemoryRegionSource(Arc<Mutex<BTreeMap<Position, Vec<u8>>>>);
+
+    impl RegionSource for MemoryRegionSource {
+        fn read_region(&self, pos: Position) -> crate::Result<Vec<u8>> {
+            self.0.lock().unwrap().get(&pos).cloned().ok_or_else(|| io::Error::from(io::ErrorKind::NotFound).into())
+        }
+
+        fn region_positions(&self) -> Vec<Position> {
+            self.0.lock().unwrap().keys().copied().collect()
+        }
+
+        fn write_region(&self, pos: Position, data: &[u8]) -> crate::Result<()> {
+            self.0.lock().unwrap().insert(pos, data.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn save_edited_chunks() {
+        let test_data = fs::read(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_files/r.0.0.mca")).expect("Failed to open test file");
+        let original = Region::parse_region(test_data.clone()).unwrap();
+        let chunk_pos = *original.chunks()[0].chunk_pos();
+        let regions = Arc::new(Mutex::new(BTreeMap::from([(Position::new(0, 0, 0), test_data)])));
+        let mut world = World::load("test_files/world").unwrap();
+        world.set_region_source(Box::new(MemoryRegionSource(regions.clone())));
+
+        let pos = Position::new(chunk_pos.x*16 + 3, 70, chunk_pos.z*16 + 5);
+        let gold = "minecraft:gold_block".parse::<Block>().unwrap();
+        assert!(world.set_block(pos, gold.clone()));
+        assert_eq!(world.dirty_chunks(), vec![Position::new(chunk_pos.x, 0, chunk_pos.z)]);
+        world.save().unwrap();
+        assert!(world.dirty_chunks().is_empty());
+
+        let saved = Region::parse_region(regions.lock().unwrap()[&Position::new(0, 0, 0)].clone()).unwrap();
+        assert_eq!(saved.get(pos), Some(gold));
+        assert_eq!(saved.chunks().len(), original.chunks().len());
+        assert!(saved.timestamp_for(chunk_pos.x, chunk_pos.z).unwrap().modified_seconds() > 0);
+    }
+
+    #[test]
+    fn reload_drops_edits() {
+        let test_data = fs::read(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_files/r.0.0.mca")).expect("Failed to open test file");
+        let chunk_pos = *Region::parse_region(test_data.clone()).unwrap().chunks()[0].chunk_pos();
+        let regions = Arc::new(Mutex::new(BTreeMap::from([(Position::new(0, 0, 0), test_data)])));
+        let mut world = World::load("test_files/world").unwrap();
+        world.set_region_source(Box::new(MemoryRegionSource(regions)));
+
+        let pos = Position::new(chunk_pos.x*16 + 3, 70, chunk_pos.z*16 + 5);
+        let original = world.get_block(pos);
+        assert!(world.set_block(pos, "minecraft:gold_block".parse().unwrap()));
+        world.reload_region(Position::new(0, 0, 0)).unwrap();
+        assert!(world.dirty_chunks().is_empty());
+        assert_eq!(world.get_block(pos), original);
+    }
+
+    #[test]
+    fn block_query_errors() {
+        let stone = "minecraft:stone".parse::<Block>().unwrap();
+        let mut top = Section::empty(2);
+        top.fill(stone.clone());
+        let mut chunk = Chunk::new(3700, Position::new(0, 0, 0), "minecraft:full".to_string(), vec![Section::empty(0), top], NbtTag::Compound(String::new(), vec![]));
+        chunk.drop_empty_sections();
+        assert_eq!(chunk.try_get(Position::new(1, 40, 1)), Ok(Some(&stone)));
+        assert_eq!(chunk.try_get(Position::new(1, 5, 1)).unwrap().map(|block| block.is_air()), Some(true));
+        // Sections that were never stored are air too, like with get_ref
+        assert_eq!(chunk.try_get(Position::new(1, 20, 1)), Ok(chunk.get_ref(Position::new(1, 20, 1))));
+        assert_eq!(chunk.try_get(Position::new(1, 20, 1)).unwrap().map(|block| block.is_air()), Some(true));
+        assert_eq!(chunk.try_get(Position::new(1, 48, 1)), Err(BlockQueryError::OutOfVerticalRange(48)));
+
+        let mut region = Region::new();
+        region.insert_chunk(Position::new(0, 0, 0), chunk).unwrap();
+        assert_eq!(region.try_get(Position::new(17, 40, 1)), Err(BlockQueryError::ChunkNotGenerated(Position::new(1, 0, 0))));
+    }
+
+    #[test]
+    fn chunk_key() {
+        let pos = Position::new(-3, 0, 7);
+        assert_eq!(pos.to_chunk_key(), 0x0000_0007_FFFF_FFFD);
+        assert_eq!(Position::from_chunk_key(pos.to_chunk_key()), pos);
+        assert_eq!(Position::from_chunk_key(Position::new(5, 0, -1).to_chunk_key()), Position::new(5, 0, -1));
+    }
+
+    #[test]
+    fn position_distance() {
+        let a = Position::new(0, 0, 0);
+        assert_eq!(a.distance(&Position::new(3, 4, 0)), 5.0);
+        assert_eq!(a.manhattan_distance(&Position::new(3, -4, 1)), 8);
+        assert_eq!(Position::new(i32::MIN, 0, 0).manhattan_distance(&Position::new(i32::MAX, 0, 0)), u32::MAX as i64);
+    }
+
+    #[test]
+    fn block_from_str() {
+        assert_eq!("minecraft:stone".parse::<Block>().unwrap(), Block { identifier: "minecraft:stone".to_string(), properties: BTreeMap::new() });
+        assert_eq!(
+            "minecraft:oak_stairs[facing=north,half=bottom]".parse::<Block>().unwrap().properties,
+            BTreeMap::from([("facing".to_string(), "north".to_string()), ("half".to_string(), "bottom".to_string())])
+        );
+        assert!("minecraft:oak_stairs[facing=north".parse::<Block>().is_err());
+        assert!("[facing=north]".parse::<Block>().is_err());
+    }
+
+    #[test]
+    fn region_header_validation() {
+        let test_data = fs::read(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_files/r.0.0.mca")).expect("Failed to open test file");
+        assert!(Region::is_valid_header(&test_data));
+        assert!(!Region::is_valid_header(&test_data[..4096]));
+
+        let mut header_collision = vec![0; 0x2000];
+        header_collision[3] = 1;
+        assert!(!Region::is_valid_header(&header_collision));
+    }
+
+    #[test]
+    #[cfg(feature = "block_tables")]
+    fn opaque_full_cubes() {
+        let opaque = |identifier: &str| identifier.parse::<Block>().unwrap().is_opaque_full_cube();
+        assert!(opaque("minecraft:stone"));
+        assert!(opaque("minecraft:oak_planks"));
+        assert!(!opaque("minecraft:glass"));
+        assert!(!opaque("minecraft:oak_stairs[facing=north]"));
+        assert!(!opaque("minecraft:potted_cactus"));
+        assert!(!opaque("minecraft:soul_lantern"));
+        assert!(opaque("minecraft:sea_lantern"));
+        assert!(opaque("minecraft:jack_o_lantern"));
+        assert!(opaque("minecraft:mud"));
+        assert!(opaque("minecraft:soul_sand"));
+        assert!(!opaque("minecraft:brain_coral_wall_fan[facing=north]"));
+        assert!(!opaque("minecraft:dead_tube_coral_wall_fan"));
+        for plant in ["mangrove_propagule", "pale_hanging_moss", "open_eyeblossom", "closed_eyeblossom", "resin_clump",
+            "short_dry_grass", "tall_dry_grass", "cactus_flower"] {
+            assert!(!opaque(&format!("minecraft:{}", plant)), "{} is not a full cube", plant);
+        }
+        assert!(!opaque("minecraft:air"));
+        assert!(!opaque("othermod:stone"));
+    }
+
+    #[test]
+    #[cfg(feature = "block_tables")]
+    fn block_normalization() {
+        let block = "minecraft:note_block[instrument=harp,note=0,powered=true]".parse::<Block>().unwrap();
+        assert_eq!(block.normalized(), "minecraft:note_block[powered=true]".parse::<Block>().unwrap());
+        let unknown = "othermod:machine[powered=false]".parse::<Block>().unwrap();
+        assert_eq!(unknown.normalized(), unknown);
+    }
+
+    #[test]
+    fn morton_order() {
+        let mut section = Section::empty(0);
+        section.set(Position::new(3, 5, 7), "minecraft:stone".parse().unwrap());
+        let order = section.iter_morton().map(|(pos, _)| pos).collect::<Vec<Position>>();
+        assert_eq!(order[..4], [Position::new(0, 0, 0), Position::new(1, 0, 0), Position::new(0, 1, 0), Position::new(1, 1, 0)]);
+        assert_eq!(order.iter().collect::<BTreeSet<&Position>>().len(), 4096);
+        let stone = section.iter_morton().filter(|(_, block)| !block.is_air()).map(|(pos, _)| pos).collect::<Vec<Position>>();
+        assert_eq!(stone, vec![Position::new(3, 5, 7)]);
+    }
+
+    #[test]
+    fn section_biomes() {
+        let section_with_biomes = |biomes: Vec<NbtTag>| {
+            let air = NbtTag::Compound(String::new(), vec![NbtTag::String("Name".to_string(), "minecraft:air".to_string())]);
+            let tag = NbtTag::Compound(String::new(), vec![
+                NbtTag::Byte("Y".to_string(), 0),
+                NbtTag::Compound("block_states".to_string(), vec![NbtTag::List("palette".to_string(), vec![air])]),
+                NbtTag::Compound("biomes".to_string(), biomes),
+            ]);
+            Section::parse_any(tag, &mut vec![], 3700).unwrap().unwrap()
+        };
+        let biome_palette = |names: &[&str]| NbtTag::List("palette".to_string(), names.iter().map(|name| NbtTag::String(String::new(), name.to_string())).collect());
+
+        let uniform = section_with_biomes(vec![biome_palette(&["minecraft:plains"])]);
+        assert_eq!(uniform.biome(Position::new(15, 15, 15)), Some("minecraft:plains"));
+
+        let mixed = section_with_biomes(vec![biome_palette(&["minecraft:plains", "minecraft:river"]), NbtTag::LongArray("data".to_string(), vec![1])]);
+        assert_eq!(mixed.biome(Position::new(3, 3, 3)), Some("minecraft:river"));
+        assert_eq!(mixed.biome(Position::new(4, 0, 0)), Some("minecraft:plains"));
+
+        assert_eq!(Section::empty(0).biome(Position::new(0, 0, 0)), None);
+    }
+
+    #[test]
+    #[cfg(feature = "testutil")]
+    fn flat_region() {
+        let stone = "minecraft:stone".parse::<Block>().unwrap();
+        let region = Region::parse_region(crate::testutil::make_flat_region(stone.clone(), 20)).unwrap();
+        assert_eq!(region.chunks().len(), 1024);
+        assert_eq!(region.get(Position::new(100, -45, 511)), Some(stone));
+        assert_eq!(region.get_identifier(Position::new(100, -44, 511)), Some("minecraft:air"));
+        assert_eq!(region.chunks()[0].sections()[0].biome(Position::new(0, 0, 0)), Some("minecraft:plains"));
+    }
+
+    #[test]
+    fn chunk_status_order() {
+        assert!(ChunkStatus::from_name("minecraft:full").unwrap() > ChunkStatus::Light);
+        assert!(ChunkStatus::from_name("surface").unwrap() < ChunkStatus::Features);
+        assert!(ChunkStatus::InitializeLight < ChunkStatus::Light);
+        assert_eq!(ChunkStatus::from_name("minecraft:unknown"), None);
     }
 
     #[test]
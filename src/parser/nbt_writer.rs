@@ -0,0 +1,149 @@
+//! Encoder for binary NBT, the counterpart to the parsers of `inbt`, used to write edited chunks back to regions.
+
+use std::io::Write;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use inbt::NbtTag;
+use crate::{McaParseError, Result};
+
+const TAG_END: u8 = 0;
+const TAG_BYTE: u8 = 1;
+const TAG_SHORT: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_LONG: u8 = 4;
+const TAG_FLOAT: u8 = 5;
+const TAG_DOUBLE: u8 = 6;
+const TAG_BYTE_ARRAY: u8 = 7;
+const TAG_STRING: u8 = 8;
+const TAG_LIST: u8 = 9;
+const TAG_COMPOUND: u8 = 10;
+const TAG_INT_ARRAY: u8 = 11;
+const TAG_LONG_ARRAY: u8 = 12;
+
+/// Encodes `tag` as a named root tag, like the payload of an uncompressed chunk.
+pub(crate) fn write_nbt(tag: &NbtTag) -> Result<Vec<u8>> {
+    let mut out = vec![];
+    let (tag_type, name) = type_and_name(tag)?;
+    out.push(tag_type);
+    write_string(&mut out, name)?;
+    write_payload(&mut out, tag)?;
+    Ok(out)
+}
+
+/// Encodes `tag` into a chunk payload for region files: the length, compression type 2 (zlib) and the compressed
+/// NBT, without the padding to whole sectors.
+pub(crate) fn write_chunk_payload(tag: &NbtTag) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    encoder.write_all(&write_nbt(tag)?)?;
+    let compressed = encoder.finish()?;
+
+    let mut payload = Vec::with_capacity(compressed.len() + 5);
+    payload.extend_from_slice(&(compressed.len() as u32 + 1).to_be_bytes());
+    payload.push(2);
+    payload.extend_from_slice(&compressed);
+    Ok(payload)
+}
+
+fn type_and_name(tag: &NbtTag) -> Result<(u8, &str)> {
+    let (tag_type, name) = match tag {
+        NbtTag::Byte(name, _) => (TAG_BYTE, name),
+        NbtTag::Short(name, _) => (TAG_SHORT, name),
+        NbtTag::Int(name, _) => (TAG_INT, name),
+        NbtTag::Long(name, _) => (TAG_LONG, name),
+        NbtTag::Float(name, _) => (TAG_FLOAT, name),
+        NbtTag::Double(name, _) => (TAG_DOUBLE, name),
+        NbtTag::ByteArray(name, _) => (TAG_BYTE_ARRAY, name),
+        NbtTag::String(name, _) => (TAG_STRING, name),
+        NbtTag::List(name, _) => (TAG_LIST, name),
+        NbtTag::Compound(name, _) => (TAG_COMPOUND, name),
+        NbtTag::IntArray(name, _) => (TAG_INT_ARRAY, name),
+        NbtTag::LongArray(name, _) => (TAG_LONG_ARRAY, name),
+        #[allow(unreachable_patterns)]
+        _ => return Err(McaParseError::NbtEncodeError(format!("{:?}", tag))),
+    };
+    Ok((tag_type, name.as_str()))
+}
+
+fn write_payload(out: &mut Vec<u8>, tag: &NbtTag) -> Result<()> {
+    match tag {
+        NbtTag::Byte(_, value) => out.push(*value as u8),
+        NbtTag::Short(_, value) => out.extend_from_slice(&value.to_be_bytes()),
+        NbtTag::Int(_, value) => out.extend_from_slice(&value.to_be_bytes()),
+        NbtTag::Long(_, value) => out.extend_from_slice(&value.to_be_bytes()),
+        NbtTag::Float(_, value) => out.extend_from_slice(&value.to_be_bytes()),
+        NbtTag::Double(_, value) => out.extend_from_slice(&value.to_be_bytes()),
+        NbtTag::ByteArray(_, values) => {
+            write_length(out, values.len())?;
+            out.extend(values.iter().map(|value| *value as u8));
+        }
+        NbtTag::String(_, value) => write_string(out, value)?,
+        NbtTag::List(_, elements) => {
+            // Empty lists have no elements to take the type from and are stored as lists of end tags
+            let element_type = match elements.first() {
+                Some(element) => type_and_name(element)?.0,
+                None => TAG_END,
+            };
+            out.push(element_type);
+            write_length(out, elements.len())?;
+            for element in elements {
+                if type_and_name(element)?.0 != element_type {
+                    return Err(McaParseError::NbtEncodeError("list with elements of different types".to_string()));
+                }
+                write_payload(out, element)?;
+            }
+        }
+        NbtTag::Compound(_, children) => {
+            for child in children {
+                let (tag_type, name) = type_and_name(child)?;
+                out.push(tag_type);
+                write_string(out, name)?;
+                write_payload(out, child)?;
+            }
+            out.push(TAG_END);
+        }
+        NbtTag::IntArray(_, values) => {
+            write_length(out, values.len())?;
+            for value in values {
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        NbtTag::LongArray(_, values) => {
+            write_length(out, values.len())?;
+            for value in values {
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        #[allow(unreachable_patterns)]
+        _ => return Err(McaParseError::NbtEncodeError(format!("{:?}", tag))),
+    }
+    Ok(())
+}
+
+/// Writes the length of a list or array, which NBT stores as a signed int
+fn write_length(out: &mut Vec<u8>, len: usize) -> Result<()> {
+    let len = i32::try_from(len).map_err(|_| McaParseError::NbtEncodeError(format!("list or array of {} elements is too long", len)))?;
+    out.extend_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+/// Writes a string in the modified UTF-8 NBT uses, where NUL takes two bytes and characters outside of the basic
+/// plane are written as two 3 byte surrogates. Strings are prefixed by their length as an unsigned short, so they
+/// can't be longer than 65535 bytes once encoded.
+fn write_string(out: &mut Vec<u8>, string: &str) -> Result<()> {
+    let mut bytes = Vec::with_capacity(string.len());
+    for c in string.chars() {
+        match c {
+            '\0' => bytes.extend_from_slice(&[0xC0, 0x80]),
+            c if c as u32 >= 0x10000 => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    bytes.extend_from_slice(&[0xE0 | (*unit >> 12) as u8, 0x80 | ((*unit >> 6) & 0x3F) as u8, 0x80 | (*unit & 0x3F) as u8]);
+                }
+            }
+            c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    let len = u16::try_from(bytes.len()).map_err(|_| McaParseError::NbtEncodeError(format!("string of {} bytes is too long", bytes.len())))?;
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(&bytes);
+    Ok(())
+}
@@ -1,5 +1,9 @@
 use std::io;
 use thiserror::Error;
+use crate::Position;
+
+/// Result type used throughout the crate, defaulting to [`McaParseError`] as the error.
+pub type Result<T, E = McaParseError> = std::result::Result<T, E>;
 
 #[derive(Error, Debug)]
 pub enum McaParseError {
@@ -11,4 +15,35 @@ pub enum McaParseError {
     InvalidWorld,
     #[error("Hit end of data")]
     EndOfData,
-}
\ No newline at end of file
+    #[error("Failed decompressing chunk: {0}")]
+    DecompressionError(io::Error),
+    #[error("Unsupported chunk compression type: {0}")]
+    UnsupportedCompression(i8),
+    #[error("Invalid block state: {0}")]
+    InvalidBlockState(String),
+    #[error("Invalid schematic: {0}")]
+    InvalidSchematic(String),
+    #[error("Invalid section: {0}")]
+    InvalidSection(String),
+    #[error("Chunk position out of range: {0}")]
+    InvalidChunkPosition(Position),
+    #[error("Chunk location out of bounds: offset {offset}, {sectors} sectors")]
+    InvalidChunkLocation { offset: usize, sectors: usize },
+    #[error("Chunk decompresses to more than the limit of {0} bytes")]
+    ChunkTooLarge(usize),
+    #[error("Chunk {0} needs more than the 255 sectors a region file can store")]
+    ChunkTooLargeToWrite(Position),
+    #[error("Can't encode NBT: {0}")]
+    NbtEncodeError(String),
+    #[error("Unsupported chunk data version {0}, chunks from before 1.13 need the legacy feature")]
+    UnsupportedVersion(i32),
+}
+
+/// Why a block lookup didn't find a block
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum BlockQueryError {
+    #[error("Chunk {0} is not generated")]
+    ChunkNotGenerated(Position),
+    #[error("Y {0} is outside of the chunk")]
+    OutOfVerticalRange(i32),
+}
@@ -1,12 +1,49 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::iter::Peekable;
 use std::slice::Iter;
 use inbt::NbtTag;
 use log::warn;
-use crate::{Block, McaParseError, Position};
-use crate::parser::chunk::Chunk;
+use crate::{Block, BlockQueryError, BoundingBox, McaParseError, Position, Result};
+use crate::parser::chunk::{Chunk, ChunkKeys};
+use crate::parser::compression::{Decompressor, InbtDecompressor};
+use crate::parser::nbt_writer;
 use crate::parser::section::Section;
 
-#[derive(Debug)]
+/// Options for parsing chunks, see [`Region::parse_region_with_options`].
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Drops the sections that are only air after parsing a chunk to save memory, lookups still return air for them
+    pub drop_empty_sections: bool,
+    /// Keeps the stored chunk payloads around for [`Region::raw_chunk_bytes`]
+    pub retain_raw_chunks: bool,
+    /// Skips chunks that fail to parse, like a truncated last chunk, instead of failing the whole region. The
+    /// skipped chunks are listed in [`Region::skipped_chunks`].
+    pub skip_failed_chunks: bool,
+    /// Most bytes a chunk may decompress to before failing with [`McaParseError::ChunkTooLarge`], as a safeguard
+    /// against compression bombs in untrusted files. Defaults to [`ParseOptions::DEFAULT_MAX_CHUNK_BYTES`].
+    pub max_chunk_bytes: usize,
+}
+
+impl ParseOptions {
+    /// 16MiB, far more than vanilla chunks decompress to
+    pub const DEFAULT_MAX_CHUNK_BYTES: usize = 16*1024*1024;
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            drop_empty_sections: false,
+            retain_raw_chunks: false,
+            skip_failed_chunks: false,
+            max_chunk_bytes: Self::DEFAULT_MAX_CHUNK_BYTES,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ChunkLocation {
     /// Offset in 4KiB sectors from the start of the file.
     ///
@@ -18,39 +55,281 @@ pub struct ChunkLocation {
     sectors: usize,
 }
 
-#[derive(Debug)]
+impl ChunkLocation {
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn sectors(&self) -> usize {
+        self.sectors
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.offset != 0 || self.sectors != 0
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ChunkTimestamp {
     /// Represents the last modification time of a chunk in epoch seconds.
     modified_seconds: u32,
 }
 
+impl ChunkTimestamp {
+    pub fn modified_seconds(&self) -> u32 {
+        self.modified_seconds
+    }
+}
+
 #[derive(Debug)]
 pub struct Region {
     chunk_location_offsets: Vec<ChunkLocation>,
     chunk_timestamps: Vec<ChunkTimestamp>,
     chunks: Vec<Chunk>,
+    /// Stored chunk payloads by slot, only kept when parsing with [`ParseOptions::retain_raw_chunks`]
+    raw_chunks: BTreeMap<usize, Vec<u8>>,
+    /// Local chunk positions and errors of the chunks skipped with [`ParseOptions::skip_failed_chunks`]
+    skipped_chunks: Vec<(Position, McaParseError)>,
+}
+
+impl Default for Region {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Region {
+    /// Creates a region without any chunks.
+    pub fn new() -> Region {
+        Region {
+            chunk_location_offsets: (0..1024).map(|_| ChunkLocation { offset: 0, sectors: 0 }).collect(),
+            chunk_timestamps: (0..1024).map(|_| ChunkTimestamp { modified_seconds: 0 }).collect(),
+            chunks: vec![],
+            raw_chunks: BTreeMap::new(),
+            skipped_chunks: vec![],
+        }
+    }
+
+    /// Inserts a chunk at a local chunk position (0..32 on x and z), replacing the chunk already there.
+    ///
+    /// The chunk's own position has to map to the same slot, i.e. be equal to `local` modulo 32.
+    pub fn insert_chunk(&mut self, local: Position, chunk: Chunk) -> Result<()> {
+        if !(0..32).contains(&local.x) || !(0..32).contains(&local.z)
+            || chunk.chunk_pos().x.rem_euclid(32) != local.x || chunk.chunk_pos().z.rem_euclid(32) != local.z {
+            return Err(McaParseError::InvalidChunkPosition(local));
+        }
+        let slot = Self::slot_index(&local);
+        self.chunks.retain(|c| Self::slot_index(c.chunk_pos()) != slot);
+        self.chunks.push(chunk);
+        // The stored payload and its location are for the replaced chunk, the new one is laid out when writing
+        self.raw_chunks.remove(&slot);
+        self.chunk_location_offsets[slot] = ChunkLocation { offset: 0, sectors: 0 };
+        Ok(())
+    }
+
+    /// Copies the chunks of `other` into this region, replacing the chunks already present if `overwrite` is set,
+    /// otherwise only filling empty slots. Copied chunks take over their timestamp and stored payload, their
+    /// sectors in `other` don't mean anything here so they're laid out again by [`Region::write_to`].
+    pub fn merge(&mut self, mut other: Region, overwrite: bool) {
+        for chunk in other.chunks {
+            let slot = Self::slot_index(chunk.chunk_pos());
+            match self.chunks.iter().position(|c| Self::slot_index(c.chunk_pos()) == slot) {
+                Some(_) if !overwrite => continue,
+                Some(index) => self.chunks[index] = chunk,
+                None => self.chunks.push(chunk),
+            }
+            self.chunk_location_offsets[slot] = ChunkLocation { offset: 0, sectors: 0 };
+            self.chunk_timestamps[slot] = other.chunk_timestamps[slot].clone();
+            match other.raw_chunks.remove(&slot) {
+                Some(raw) => self.raw_chunks.insert(slot, raw),
+                None => self.raw_chunks.remove(&slot),
+            };
+        }
+    }
+
+    /// Copies the chunks whose local position (0..32 on x and z) is inside of `chunk_bounds` into a new region, the
+    /// y of the bounds is ignored. The location table is recomputed to pack the copied chunks one after another
+    /// behind the header, keeping their sector counts.
+    pub fn subregion(&self, chunk_bounds: BoundingBox) -> Region {
+        let mut region = Region::new();
+        let mut offset = 2;
+        for chunk in &self.chunks {
+            let local = Position::new(chunk.chunk_pos().x.rem_euclid(32), chunk_bounds.min.y, chunk.chunk_pos().z.rem_euclid(32));
+            if !chunk_bounds.contains(&local) {
+                continue;
+            }
+            let slot = Self::slot_index(chunk.chunk_pos());
+            let raw = self.raw_chunks.get(&slot);
+            let sectors = raw.map_or(self.chunk_location_offsets[slot].sectors, |raw| raw.len().div_ceil(4096));
+            region.chunk_location_offsets[slot] = ChunkLocation { offset, sectors };
+            region.chunk_timestamps[slot] = self.chunk_timestamps[slot].clone();
+            if let Some(raw) = raw {
+                region.raw_chunks.insert(slot, raw.clone());
+            }
+            region.chunks.push(chunk.clone());
+            offset += sectors;
+        }
+        region
+    }
+
+    /// Writes the region in the region file format, streaming the header and each chunk straight to `writer`.
+    ///
+    /// Every slot with a chunk or a stored payload (see [`ParseOptions::retain_raw_chunks`]) is written, packed one
+    /// after another after the header in slot order. Stored payloads are written as they were, including the ones of
+    /// chunks that weren't parsed, while the chunks without one are encoded with [`Chunk::to_nbt`] and compressed
+    /// with zlib. Only one encoded chunk is held at a time, so they're encoded twice: once to lay them out for the
+    /// header, and again when writing them.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        let mut slots = self.chunks.iter()
+            .map(|chunk| (Self::slot_index(chunk.chunk_pos()), Some(chunk)))
+            .collect::<BTreeMap<usize, Option<&Chunk>>>();
+        for slot in self.raw_chunks.keys() {
+            slots.entry(*slot).or_insert(None);
+        }
+
+        let mut locations = vec![];
+        let mut offset = 2;
+        for (&slot, &chunk) in &slots {
+            let sectors = self.chunk_payload(slot, chunk)?.len().div_ceil(4096);
+            if sectors > 255 {
+                let local = Position::new((slot%32) as i32, 0, (slot/32) as i32);
+                return Err(McaParseError::ChunkTooLargeToWrite(chunk.map_or(local, |chunk| *chunk.chunk_pos())));
+            }
+            locations.push(ChunkLocation { offset, sectors });
+            offset += sectors;
+        }
+
+        let mut header = vec![0; 8192];
+        for (slot, location) in slots.keys().zip(&locations) {
+            header[slot*4..slot*4 + 3].copy_from_slice(&(location.offset as u32).to_be_bytes()[1..]);
+            header[slot*4 + 3] = location.sectors as u8;
+            header[4096 + slot*4..4096 + slot*4 + 4].copy_from_slice(&self.chunk_timestamps[*slot].modified_seconds.to_be_bytes());
+        }
+        writer.write_all(&header)?;
+        for ((&slot, &chunk), location) in slots.iter().zip(&locations) {
+            let payload = self.chunk_payload(slot, chunk)?;
+            writer.write_all(&payload)?;
+            writer.write_all(&vec![0; location.sectors*4096 - payload.len()])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// The payload [`Region::write_to`] writes for a slot, either the stored one or the chunk encoded again
+    fn chunk_payload(&self, slot: usize, chunk: Option<&Chunk>) -> Result<Cow<'_, [u8]>> {
+        match (self.raw_chunks.get(&slot), chunk) {
+            (Some(raw), _) => Ok(Cow::Borrowed(raw.as_slice())),
+            (None, Some(chunk)) => Ok(Cow::Owned(nbt_writer::write_chunk_payload(&chunk.to_nbt()?)?)),
+            // Only slots with a chunk or a stored payload are written
+            (None, None) => Ok(Cow::Borrowed(&[])),
+        }
+    }
+
+    /// Gets the sectors a chunk location points to, or an error if they're outside of the data or point into the
+    /// two header sectors.
+    ///
+    /// The last chunk in a file isn't always padded to a whole sector, so the sectors may be cut short by the end
+    /// of the data as long as they start inside of it.
+    fn chunk_sectors<'a>(data: &'a [u8], loc: &ChunkLocation) -> Result<&'a [u8]> {
+        let out_of_bounds = || McaParseError::InvalidChunkLocation { offset: loc.offset, sectors: loc.sectors };
+        let start = loc.offset.checked_mul(4096).ok_or_else(out_of_bounds)?;
+        let end = loc.sectors.checked_mul(4096).and_then(|len| start.checked_add(len)).ok_or_else(out_of_bounds)?;
+        if loc.offset < 2 || start >= data.len() {
+            return Err(out_of_bounds());
+        }
+        data.get(start..end.min(data.len())).ok_or_else(out_of_bounds)
+    }
+
+    /// The stored payload of the chunk at a local chunk position (0..32 on x and z), still compressed and with the
+    /// length and compression type header, but without the padding to whole sectors.
+    ///
+    /// Only available when the region was parsed with [`ParseOptions::retain_raw_chunks`], and dropped once the chunk
+    /// is replaced or borrowed through [`Region::get_chunk_mut`].
+    pub fn raw_chunk_bytes(&self, local_x: i32, local_z: i32) -> Option<&[u8]> {
+        if !(0..32).contains(&local_x) || !(0..32).contains(&local_z) {
+            return None;
+        }
+        self.raw_chunks.get(&((local_x + local_z*32) as usize)).map(|raw| raw.as_slice())
+    }
+
+    /// The local chunk positions (0..32 on x and z) of the chunks that failed to parse and were skipped, together
+    /// with why they failed. Only chunks parsed with [`ParseOptions::skip_failed_chunks`] are skipped.
+    pub fn skipped_chunks(&self) -> &Vec<(Position, McaParseError)> {
+        &self.skipped_chunks
+    }
+
+    /// Cuts the sector padding off of a chunk payload, using the length in its header
+    fn trim_payload(chunk_data: &[u8]) -> &[u8] {
+        let length = chunk_data.get(0..4).map_or(0, |l| u32::from_be_bytes([l[0], l[1], l[2], l[3]]) as usize);
+        &chunk_data[..length.saturating_add(4).min(chunk_data.len())]
+    }
+
+    /// Index into the location and timestamp tables of a chunk position, either absolute or local to the region
+    fn slot_index(chunk_pos: &Position) -> usize {
+        (chunk_pos.x.rem_euclid(32) + chunk_pos.z.rem_euclid(32)*32) as usize
+    }
+
     pub fn chunk_location_offsets(&self) -> &Vec<ChunkLocation> {
         &self.chunk_location_offsets
     }
     pub fn chunk_timestamps(&self) -> &Vec<ChunkTimestamp> {
         &self.chunk_timestamps
     }
+    /// Gets the timestamp of the chunk at a local chunk position (0..32 on x and z).
+    pub fn timestamp_for(&self, local_x: i32, local_z: i32) -> Option<&ChunkTimestamp> {
+        if !(0..32).contains(&local_x) || !(0..32).contains(&local_z) {
+            return None;
+        }
+        self.chunk_timestamps.get((local_x + local_z*32) as usize)
+    }
+    /// Sets the timestamp of the chunk at a local chunk position (0..32 on x and z) to `modified_seconds` since the
+    /// epoch. Positions outside of the region are ignored.
+    pub fn set_timestamp(&mut self, local_x: i32, local_z: i32, modified_seconds: u32) {
+        if (0..32).contains(&local_x) && (0..32).contains(&local_z) {
+            self.chunk_timestamps[(local_x + local_z*32) as usize] = ChunkTimestamp { modified_seconds };
+        }
+    }
+    /// Number of chunks in the region, including inserted ones but not the ones left out when parsing
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+    /// Number of 4KiB sectors used by chunks in the location table, i.e. the chunk data size on disk
+    pub fn populated_sector_count(&self) -> usize {
+        self.chunk_location_offsets.iter().filter(|loc| loc.is_present()).map(|loc| loc.sectors).sum()
+    }
     pub fn chunks(&self) -> &Vec<Chunk> {
         &self.chunks
     }
+    pub fn into_chunks(self) -> Vec<Chunk> {
+        self.chunks
+    }
 }
 
 impl Region {
     /// Gets block relative to region origin
     pub fn get(&self, pos: Position) -> Option<Block> {
+        self.get_ref(pos).cloned()
+    }
+
+    /// Same as [`Region::get`], but borrows the block instead of cloning it
+    pub fn get_ref(&self, pos: Position) -> Option<&Block> {
         let chunk = self.get_chunk(pos.chunk_in_region());
         if chunk.is_none() {
             warn!("Warning: chunk {} doesnt exist", pos.chunk_in_region());
         }
-        chunk?.get(pos)
+        chunk?.get_ref(pos)
+    }
+
+    /// Same as [`Region::get_ref`], but tells why there's no block instead of logging a warning and returning `None`.
+    /// See [`Chunk::try_get`] for sections that aren't stored.
+    pub fn try_get(&self, pos: Position) -> Result<Option<&Block>, BlockQueryError> {
+        let chunk_pos = pos.chunk_in_region();
+        self.get_chunk(chunk_pos).ok_or(BlockQueryError::ChunkNotGenerated(chunk_pos))?.try_get(pos)
+    }
+
+    /// Gets just the identifier of the block, e.g. `minecraft:stone`
+    pub fn get_identifier(&self, pos: Position) -> Option<&str> {
+        self.get_ref(pos).map(|block| block.identifier.as_str())
     }
 
     pub fn get_chunk(&self, pos: Position) -> Option<&Chunk> {
@@ -62,86 +341,365 @@ impl Region {
         None
     }
 
-    fn next(iterable: &mut Peekable<Iter<u8>>) -> Result<u8, McaParseError> {
+    /// Same as [`Region::get_chunk`], but borrows the chunk mutably for editing
+    ///
+    /// The stored payload of the chunk is dropped, so [`Region::write_to`] encodes it again with the edits.
+    pub fn get_chunk_mut(&mut self, pos: Position) -> Option<&mut Chunk> {
+        let chunk = self.chunks.iter_mut().find(|chunk| chunk.chunk_pos().x == pos.x && chunk.chunk_pos().z == pos.z)?;
+        self.raw_chunks.remove(&Self::slot_index(chunk.chunk_pos()));
+        Some(chunk)
+    }
+
+    /// Iterates over every section of every chunk, together with the world position of the section's lowest corner.
+    pub fn iter_sections(&self) -> impl Iterator<Item = (Position, &Section)> {
+        self.chunks.iter().flat_map(|chunk| {
+            let chunk_pos = *chunk.chunk_pos();
+            chunk.sections().iter().map(move |section| {
+                (Position::new(chunk_pos.x*16, section.y() as i32*16, chunk_pos.z*16), section)
+            })
+        })
+    }
+
+    /// Collects the identifier of every block in the region by merging the section palettes, without looking at
+    /// the blocks themselves.
+    pub fn distinct_blocks(&self) -> BTreeSet<String> {
+        self.chunks.iter().flat_map(|chunk| chunk.distinct_blocks()).collect()
+    }
+
+    /// Renders the region top-down into a 512×512 grid indexed by `z*512 + x`, using `map` to color the highest
+    /// non-air block of each column. Columns without any blocks are left black.
+    pub fn surface_colors(&self, map: impl Fn(&Block) -> [u8; 3]) -> Vec<[u8; 3]> {
+        let mut colors = vec![[0; 3]; 512*512];
+        for chunk in &self.chunks {
+            let chunk_x = chunk.chunk_pos().x.rem_euclid(32) as usize;
+            let chunk_z = chunk.chunk_pos().z.rem_euclid(32) as usize;
+            let heights = chunk.min_section_y() as i32*16..chunk.max_section_y() as i32*16 + 16;
+            for z in 0..16 {
+                for x in 0..16 {
+                    let top = heights.clone().rev()
+                        .filter_map(|y| chunk.get(Position::new(x as i32, y, z as i32)))
+                        .find(|block| !block.is_air());
+                    if let Some(block) = top {
+                        colors[(chunk_z*16 + z)*512 + chunk_x*16 + x] = map(&block);
+                    }
+                }
+            }
+        }
+        colors
+    }
+
+    fn next(iterable: &mut Peekable<Iter<u8>>) -> Result<u8> {
         iterable.next().map(|n| *n).ok_or(McaParseError::EndOfData)
     }
 
-    fn next_byte(iterable: &mut Peekable<Iter<u8>>) -> Result<i8, McaParseError> {
+    fn next_byte(iterable: &mut Peekable<Iter<u8>>) -> Result<i8> {
         Ok(i8::from_be_bytes([Self::next(iterable)?]))
     }
 
-    fn next_short(iterable: &mut Peekable<Iter<u8>>) -> Result<i16, McaParseError> {
+    fn next_short(iterable: &mut Peekable<Iter<u8>>) -> Result<i16> {
         Ok(i16::from_be_bytes([Self::next(iterable)?, Self::next(iterable)?]))
     }
 
-    fn next_int(iterable: &mut Peekable<Iter<u8>>) -> Result<i32, McaParseError> {
+    fn next_int(iterable: &mut Peekable<Iter<u8>>) -> Result<i32> {
         Ok(i32::from_be_bytes([
             Self::next(iterable)?, Self::next(iterable)?, Self::next(iterable)?, Self::next(iterable)?
         ]))
     }
 
-    fn next_long(iterable: &mut Peekable<Iter<u8>>) -> Result<i64, McaParseError> {
+    fn next_long(iterable: &mut Peekable<Iter<u8>>) -> Result<i64> {
         Ok(i64::from_be_bytes([
             Self::next(iterable)?, Self::next(iterable)?, Self::next(iterable)?, Self::next(iterable)?,
             Self::next(iterable)?, Self::next(iterable)?, Self::next(iterable)?, Self::next(iterable)?
         ]))
     }
 
-    fn next_chunk_location(iterable: &mut Peekable<Iter<u8>>) -> Result<ChunkLocation, McaParseError> {
+    fn next_chunk_location(iterable: &mut Peekable<Iter<u8>>) -> Result<ChunkLocation> {
         Ok(ChunkLocation {
             offset: u32::from_be_bytes([0, Self::next(iterable)?, Self::next(iterable)?, Self::next(iterable)?]) as usize,
             sectors: Self::next(iterable)? as usize,
         })
     }
 
-    fn next_chunk_timestamp(iterable: &mut Peekable<Iter<u8>>) -> Result<ChunkTimestamp, McaParseError> {
+    fn next_chunk_timestamp(iterable: &mut Peekable<Iter<u8>>) -> Result<ChunkTimestamp> {
         Ok(ChunkTimestamp {
             modified_seconds: u32::from_be_bytes([ Self::next(iterable)?, Self::next(iterable)?, Self::next(iterable)?, Self::next(iterable)? ])}
         )
     }
-    fn next_chunk(iterable: &mut Peekable<Iter<u8>>) -> Result<Chunk, McaParseError> {
-        let length = Self::next_int(iterable)?;
+    /// Reads the next chunk, skipping it before decoding its sections if its status doesn't pass `filter`.
+    fn next_chunk(chunk_data: &[u8], decompressor: &dyn Decompressor, filter: &dyn Fn(&str) -> bool, options: &ParseOptions) -> Result<Option<Chunk>> {
+        let parser_result = Self::next_chunk_nbt(chunk_data, decompressor, options.max_chunk_bytes)?;
+        // Some tools writing chunks leave it out, that shouldn't make the whole region unreadable
+        let data_version = parser_result.get_int("DataVersion").unwrap_or_else(|_| {
+            warn!("Chunk is missing its DataVersion");
+            Chunk::UNKNOWN_DATA_VERSION
+        });
+        #[cfg(not(feature = "legacy"))]
+        if data_version != Chunk::UNKNOWN_DATA_VERSION && data_version < Chunk::FLATTENING_DATA_VERSION {
+            return Err(McaParseError::UnsupportedVersion(data_version));
+        }
+
+        // Chunks from before 1.18 keep everything but the DataVersion inside of a Level compound
+        let level = parser_result.get("Level").ok();
+        let chunk_tag: &NbtTag = match &level {
+            Some(level) => level,
+            None => &parser_result,
+        };
+        let keys = ChunkKeys::for_version(data_version, level.is_some());
+        let status = match chunk_tag.get_string(keys.status) {
+            Ok(status) => status,
+            // Chunks from before 1.13 only have flags for the generation stages, only finished chunks are saved
+            Err(_) if data_version != Chunk::UNKNOWN_DATA_VERSION && data_version < Chunk::FLATTENING_DATA_VERSION => "minecraft:full".to_string(),
+            Err(e) => return Err(e.into()),
+        };
+        if !filter(&status) {
+            return Ok(None);
+        }
+        let sections = Self::parse_sections_for(chunk_tag.get_list(keys.sections)?, data_version)?;
+        // yPos is the lowest section of the chunk, chunks from before 1.18 don't have one
+        let min_section_y = chunk_tag.get_int(keys.y_pos)
+            .unwrap_or_else(|_| sections.iter().map(|s| s.y() as i32).min().unwrap_or(0));
+        let chunk_pos = Position {
+            x: chunk_tag.get_int(keys.x_pos)?,
+            y: min_section_y,
+            z: chunk_tag.get_int(keys.z_pos)?,
+        };
+        let mut chunk = Chunk::new(
+            data_version,
+            chunk_pos,
+            status,
+            sections,
+            parser_result,
+        );
+        if options.drop_empty_sections {
+            chunk.drop_empty_sections();
+        }
+        Ok(Some(chunk))
+    }
+
+    /// Reads and decompresses the NBT of the next chunk, without interpreting it.
+    fn next_chunk_nbt(chunk_data: &[u8], decompressor: &dyn Decompressor, max_chunk_bytes: usize) -> Result<NbtTag> {
+        let mut header = chunk_data.iter().peekable();
+        let length = Self::next_int(&mut header)?;
         // 1 - GZip (usually not used)
         // 2 - Zlib
         // 3 - Uncompressed (usually not used)
-        let compression_type = Self::next_byte(iterable)?;
-        let raw_data = iterable.take((length - 1) as usize).map(|n| *n).collect::<Vec<u8>>();
-        if raw_data.len() < (length - 1) as usize {
-            return Err(McaParseError::EndOfData);
+        // 4 - LZ4 (24w04a+)
+        let compression_type = Self::next_byte(&mut header)?;
+        // The length includes the compression type. The data is borrowed by the decompressors, only uncompressed
+        // chunks are copied since the parser needs an owned buffer
+        let raw_data = length.checked_sub(1)
+            .and_then(|len| usize::try_from(len).ok())
+            .and_then(|len| chunk_data.get(5..5 + len))
+            .ok_or(McaParseError::EndOfData)?;
+        match compression_type {
+            1 => decompressor.gzip(raw_data, max_chunk_bytes),
+            2 => decompressor.zlib(raw_data, max_chunk_bytes),
+            3 if raw_data.len() > max_chunk_bytes => Err(McaParseError::ChunkTooLarge(max_chunk_bytes)),
+            3 => Ok(inbt::nbt_parser::parse_binary(raw_data.to_vec())),
+            4 => decompressor.lz4(raw_data, max_chunk_bytes),
+            _ => Err(McaParseError::UnsupportedCompression(compression_type)),
         }
-        // TODO: convert to ParseError
-        let parser_result = match compression_type {
-            1 => inbt::nbt_parser::parse_gzip(raw_data.clone()),
-            2 => inbt::nbt_parser::parse_zlib(raw_data.clone()),
-            3 => Ok(inbt::nbt_parser::parse_binary(raw_data.clone())),
-            _ => unimplemented!()
-        }.unwrap();
-        let sections = Self::parse_sections(parser_result.get_list("sections")?)?;
-        Ok(Chunk::new(
-            parser_result.get_int("DataVersion")?,
-            Position {
-                x: parser_result.get_int("xPos")?,
-                y: parser_result.get_int("yPos")?,
-                z: parser_result.get_int("zPos")?,
-            },
-            parser_result.get_string("Status")?,
-            sections,
-        ))
     }
 
-    pub fn parse_sections(data: Vec<NbtTag>) -> Result<Vec<Section>, McaParseError> {
+    pub fn parse_sections(data: Vec<NbtTag>) -> Result<Vec<Section>> {
+        Self::parse_sections_for(data, Chunk::UNKNOWN_DATA_VERSION)
+    }
+
+    /// Parses the sections of a chunk, in whichever format each of them is stored in.
+    fn parse_sections_for(data: Vec<NbtTag>, data_version: i32) -> Result<Vec<Section>> {
         let mut sections = vec![];
+        let mut scratch = Vec::with_capacity(4096);
         for tag in data {
-            sections.push(Section::parse_section(tag)?)
+            // Sections only holding light data, and some leftovers from blending old chunks, don't have any blocks
+            sections.extend(Section::parse_any(tag, &mut scratch, data_version)?);
         }
         Ok(sections)
     }
 
-    pub fn parse_region(region_data: Vec<u8>) -> Result<Region, McaParseError> {
+    /// Reads a single chunk at a local chunk position (0..32 on x and z) from a region file, only reading its
+    /// location entry and its own sectors.
+    pub fn read_chunk<R: Read + Seek>(reader: &mut R, local_x: i32, local_z: i32) -> Result<Option<Chunk>> {
+        Self::read_chunk_with_options(reader, local_x, local_z, &ParseOptions::default())
+    }
+
+    /// Same as [`Region::read_chunk`], with options for parsing the chunk.
+    pub fn read_chunk_with_options<R: Read + Seek>(reader: &mut R, local_x: i32, local_z: i32, options: &ParseOptions) -> Result<Option<Chunk>> {
+        if !(0..32).contains(&local_x) || !(0..32).contains(&local_z) {
+            return Err(McaParseError::InvalidChunkPosition(Position::new(local_x, 0, local_z)));
+        }
+        let mut location = [0; 4];
+        reader.seek(SeekFrom::Start(((local_x + local_z*32)*4) as u64))?;
+        reader.read_exact(&mut location)?;
+        let location = Self::next_chunk_location(&mut location.iter().peekable())?;
+        if location.offset == 0 || location.sectors == 0 {
+            return Ok(None);
+        }
+
+        let mut chunk_data = vec![];
+        reader.seek(SeekFrom::Start(location.offset as u64*4096))?;
+        reader.take(location.sectors as u64*4096).read_to_end(&mut chunk_data)?;
+        Self::next_chunk(&chunk_data, &InbtDecompressor, &|_| true, options)
+    }
+
+    /// Cheap sanity check of the location table, to tell region files apart from other files before parsing.
+    ///
+    /// Region files don't have any magic bytes, but every present chunk has to start after the two header sectors,
+    /// start inside the file, and not overlap any other chunk.
+    pub fn is_valid_header(data: &[u8]) -> bool {
+        if data.len() < 0x2000 {
+            return false;
+        }
+        let mut locations = data[0..4096].iter().peekable();
+        let mut sector_ranges = vec![];
+        for _ in 0..1024 {
+            let loc = match Self::next_chunk_location(&mut locations) {
+                Ok(loc) => loc,
+                Err(_) => return false,
+            };
+            if loc.offset == 0 && loc.sectors == 0 {
+                continue;
+            }
+            if loc.offset < 2 || loc.sectors == 0 || Self::chunk_sectors(data, &loc).is_err() {
+                return false;
+            }
+            sector_ranges.push((loc.offset, loc.offset + loc.sectors));
+        }
+        sector_ranges.sort();
+        sector_ranges.windows(2).all(|w| w[0].1 <= w[1].0)
+    }
+
+    /// Decodes only the first present chunk to get its `DataVersion`.
+    ///
+    /// All chunks in a region are in practice from the same game version, so this is a cheap way to pick a parsing
+    /// path before parsing the whole region.
+    pub fn sample_data_version(data: &[u8]) -> Option<i32> {
+        let mut locations = data.get(0..4096)?.iter().peekable();
+        for _ in 0..1024 {
+            let loc = Self::next_chunk_location(&mut locations).ok()?;
+            // A location with sectors but an offset inside the header is corrupt, not missing
+            if loc.sectors != 0 {
+                let chunk_data = Self::chunk_sectors(data, &loc).ok()?;
+                let nbt = Self::next_chunk_nbt(chunk_data, &InbtDecompressor, ParseOptions::DEFAULT_MAX_CHUNK_BYTES).ok()?;
+                return nbt.get_int("DataVersion").ok();
+            }
+        }
+        None
+    }
+
+    pub fn parse_region(region_data: Vec<u8>) -> Result<Region> {
+        Self::parse_region_with(region_data, &InbtDecompressor)
+    }
+
+    /// Parses a region using a custom decompressor for the chunk data.
+    pub fn parse_region_with(region_data: Vec<u8>, decompressor: &dyn Decompressor) -> Result<Region> {
+        Self::parse_region_inner(region_data, decompressor, &|_| true, None, &ParseOptions::default())
+    }
+
+    /// Parses a region with options for parsing its chunks.
+    pub fn parse_region_with_options(region_data: Vec<u8>, options: &ParseOptions) -> Result<Region> {
+        Self::parse_region_inner(region_data, &InbtDecompressor, &|_| true, None, options)
+    }
+
+    /// Parses a region, only keeping the chunks whose status (e.g. `minecraft:full`) passes the predicate.
+    ///
+    /// The status is checked before the sections are decoded, so skipped chunks are cheap and can't fail on
+    /// missing section data.
+    pub fn parse_region_filtered(region_data: Vec<u8>, predicate: impl Fn(&str) -> bool) -> Result<Region> {
+        Self::parse_region_inner(region_data, &InbtDecompressor, &predicate, None, &ParseOptions::default())
+    }
+
+    /// Parses a region, only decoding the chunks at the given local chunk positions (0..32 on x and z). The other
+    /// chunks are skipped without decompressing them, so looking at a single chunk is cheap even in big regions.
+    pub fn parse_chunks(region_data: Vec<u8>, wanted: &[(i32, i32)]) -> Result<Region> {
+        let mut slots = BTreeSet::new();
+        for (x, z) in wanted {
+            if !(0..32).contains(x) || !(0..32).contains(z) {
+                return Err(McaParseError::InvalidChunkPosition(Position::new(*x, 0, *z)));
+            }
+            slots.insert((x + z*32) as usize);
+        }
+        Self::parse_region_inner(region_data, &InbtDecompressor, &|_| true, Some(&slots), &ParseOptions::default())
+    }
+
+    /// Reads the header and the stored payloads of a region without decompressing any chunk, e.g. to replace a few
+    /// chunks with [`Region::insert_chunk`] and write it again with [`Region::write_to`].
+    ///
+    /// Chunks whose location points outside of the data can't be copied, so they're left out with a warning.
+    pub fn parse_stored(region_data: &[u8]) -> Result<Region> {
+        if region_data.len() < 0x2000 {
+            return Err(McaParseError::EndOfData);
+        }
+        let (chunk_locations, chunk_timestamps) = Self::parse_header(&region_data[0..8192])?;
+
+        let mut raw_chunks = BTreeMap::new();
+        for (slot, loc) in chunk_locations.iter().enumerate().filter(|(_, loc)| loc.sectors != 0) {
+            match Self::chunk_sectors(region_data, loc) {
+                Ok(chunk_data) => {
+                    raw_chunks.insert(slot, Self::trim_payload(chunk_data).to_vec());
+                }
+                Err(e) => warn!("Leaving out chunk {} that can't be read: {}", Position::new((slot%32) as i32, 0, (slot/32) as i32), e),
+            }
+        }
+        Ok(Region {
+            chunk_location_offsets: chunk_locations,
+            chunk_timestamps,
+            chunks: vec![],
+            raw_chunks,
+            skipped_chunks: vec![],
+        })
+    }
+
+    /// Parses the chunks in `slots`, or all of them if it's `None`.
+    fn parse_region_inner(region_data: Vec<u8>, decompressor: &dyn Decompressor, filter: &dyn Fn(&str) -> bool, slots: Option<&BTreeSet<usize>>, options: &ParseOptions) -> Result<Region> {
         if region_data.len() < 0x2000 {
             return Err(McaParseError::EndOfData);
         }
-        let mut data = region_data[0..8192].iter().peekable();
+        let (chunk_locations, chunk_timestamps) = Self::parse_header(&region_data[0..8192])?;
+
+        let mut chunks = vec![];
+        let mut raw_chunks = BTreeMap::new();
+        let mut skipped_chunks = vec![];
+        for (slot, loc) in chunk_locations.iter().enumerate() {
+            // A location with sectors but an offset inside the header is corrupt, not missing
+            if loc.sectors == 0 || slots.is_some_and(|slots| !slots.contains(&slot)) {
+                continue;
+            }
+            let chunk_data = Self::chunk_sectors(&region_data, loc);
+            chunks.extend(Self::parse_stored_chunk(slot, chunk_data, decompressor, filter, options, &mut raw_chunks, &mut skipped_chunks)?);
+        }
+        Ok(Region {
+            chunk_location_offsets: chunk_locations,
+            chunk_timestamps,
+            chunks: chunks,
+            raw_chunks,
+            skipped_chunks,
+        })
+    }
+
+    /// Parses the sectors of the chunk in `slot`, keeping its payload if the options say so. Chunks that fail are
+    /// added to `skipped_chunks` instead with [`ParseOptions::skip_failed_chunks`].
+    fn parse_stored_chunk(slot: usize, chunk_data: Result<&[u8]>, decompressor: &dyn Decompressor, filter: &dyn Fn(&str) -> bool, options: &ParseOptions, raw_chunks: &mut BTreeMap<usize, Vec<u8>>, skipped_chunks: &mut Vec<(Position, McaParseError)>) -> Result<Option<Chunk>> {
+        let chunk = chunk_data.and_then(|chunk_data| {
+            if options.retain_raw_chunks {
+                raw_chunks.insert(slot, Self::trim_payload(chunk_data).to_vec());
+            }
+            Self::next_chunk(chunk_data, decompressor, filter, options)
+        });
+        match chunk {
+            Ok(chunk) => Ok(chunk),
+            Err(e) if options.skip_failed_chunks => {
+                let local = Position::new((slot%32) as i32, 0, (slot/32) as i32);
+                warn!("Skipping chunk {} that failed to parse: {}", local, e);
+                skipped_chunks.push((local, e));
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads the location and timestamp tables from the two header sectors.
+    fn parse_header(header: &[u8]) -> Result<(Vec<ChunkLocation>, Vec<ChunkTimestamp>)> {
+        let mut data = header.iter().peekable();
         let mut chunk_locations = vec![];
         let mut chunk_timestamps = vec![];
         for _ in 0..1024 {
@@ -150,17 +708,80 @@ impl Region {
         for _ in 0..1024 {
             chunk_timestamps.push(Self::next_chunk_timestamp(&mut data)?)
         }
+        Ok((chunk_locations, chunk_timestamps))
+    }
+
+    /// Parses a region from a reader that can't seek, like stdin, giving the same result as
+    /// [`Region::parse_region`].
+    pub fn parse_stream<R: Read>(reader: R) -> Result<Region> {
+        Self::parse_stream_with_options(reader, &InbtDecompressor, &ParseOptions::default())
+    }
 
+    /// Same as [`Region::parse_stream`], with a custom decompressor and options for parsing the chunks.
+    ///
+    /// The chunks are read in the order they're stored in, skipping the padding and unused sectors between them.
+    /// Only the sectors from the start of the current chunk on are buffered, so chunks sharing sectors with the
+    /// one before them, like in files written by some tools, can still be read.
+    pub fn parse_stream_with_options<R: Read>(mut reader: R, decompressor: &dyn Decompressor, options: &ParseOptions) -> Result<Region> {
+        let mut header = vec![0; 8192];
+        reader.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => McaParseError::EndOfData,
+            _ => McaParseError::WorldLoadError(e),
+        })?;
+        let (chunk_locations, chunk_timestamps) = Self::parse_header(&header)?;
+
+        let mut stored = chunk_locations.iter().enumerate()
+            .filter(|(_, loc)| loc.sectors != 0)
+            .collect::<Vec<(usize, &ChunkLocation)>>();
+        stored.sort_by_key(|(_, loc)| loc.offset);
+
+        // The bytes of the file from `buffer_start` on that were read but are still needed
+        let mut buffer = vec![];
+        let mut buffer_start = 8192;
         let mut chunks = vec![];
-        for loc in &chunk_locations {
-            if loc.offset != 0 && loc.sectors != 0 {
-                chunks.push(Self::next_chunk(&mut region_data[(loc.offset*4096)..(loc.offset*4096+loc.sectors*4096)].iter().peekable())?);
+        let mut raw_chunks = BTreeMap::new();
+        let mut skipped_chunks = vec![];
+        for (slot, loc) in stored {
+            let out_of_bounds = || McaParseError::InvalidChunkLocation { offset: loc.offset, sectors: loc.sectors };
+            let range = loc.offset.checked_mul(4096)
+                .filter(|_| loc.offset >= 2)
+                .and_then(|start| Some((start, start.checked_add(loc.sectors*4096)?)));
+            let chunk_data = match range {
+                Some((start, end)) => {
+                    // Later chunks start at or after this one, so everything before it can be dropped
+                    let skipped = start - buffer_start;
+                    if skipped >= buffer.len() {
+                        io::copy(&mut reader.by_ref().take((skipped - buffer.len()) as u64), &mut io::sink())?;
+                        buffer.clear();
+                    } else {
+                        buffer.drain(..skipped);
+                    }
+                    buffer_start = start;
+                    if buffer.len() < end - start {
+                        reader.by_ref().take((end - start - buffer.len()) as u64).read_to_end(&mut buffer)?;
+                    }
+                    // Like when parsing the whole file, the last chunk may be cut short but has to start inside it
+                    if buffer.is_empty() {
+                        Err(out_of_bounds())
+                    } else {
+                        Ok(&buffer[..buffer.len().min(end - start)])
+                    }
+                }
+                None => Err(out_of_bounds()),
+            };
+            if let Some(chunk) = Self::parse_stored_chunk(slot, chunk_data, decompressor, &|_| true, options, &mut raw_chunks, &mut skipped_chunks)? {
+                chunks.push((slot, chunk));
             }
         }
+        // Same order as when parsing the whole file at once
+        chunks.sort_by_key(|(slot, _)| *slot);
+        skipped_chunks.sort_by_key(|(local, _)| Self::slot_index(local));
         Ok(Region {
             chunk_location_offsets: chunk_locations,
             chunk_timestamps,
-            chunks: chunks,
+            chunks: chunks.into_iter().map(|(_, chunk)| chunk).collect(),
+            raw_chunks,
+            skipped_chunks,
         })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,135 @@
+use std::fs;
+use std::path::Path;
+use inbt::NbtTag;
+use crate::{Block, McaParseError, Position, Result};
+
+/// A Sponge schematic (`.schem`), version 2 or 3.
+#[derive(Debug, Clone)]
+pub struct Schematic {
+    width: u16,
+    height: u16,
+    length: u16,
+    palette: Vec<Block>,
+    /// Palette indexes, ordered by y, then z, then x
+    blocks: Vec<u32>,
+}
+
+impl Schematic {
+    /// Loads a schematic from a gzipped `.schem` file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Schematic> {
+        Self::parse_schematic(fs::read(path)?)
+    }
+
+    pub fn parse_schematic(schematic_data: Vec<u8>) -> Result<Schematic> {
+        let nbt = inbt::nbt_parser::parse_gzip(schematic_data)?;
+        if let Ok(schematic) = nbt.get("Schematic") {
+            // Version 3 nests everything in a "Schematic" compound and moves the block data into "Blocks"
+            let blocks = schematic.get("Blocks")?;
+            Self::from_parts(&schematic, &blocks, "Data")
+        } else {
+            Self::from_parts(&nbt, &nbt, "BlockData")
+        }
+    }
+
+    fn from_parts(schematic: &NbtTag, blocks: &NbtTag, data_name: &str) -> Result<Schematic> {
+        let width = schematic.get_short("Width")? as u16;
+        let height = schematic.get_short("Height")? as u16;
+        let length = schematic.get_short("Length")? as u16;
+
+        // The palette maps block state strings to their index
+        let entries = blocks.get_compound("Palette")?;
+        let entry_count = entries.len();
+        let mut palette = vec![];
+        for entry in entries {
+            if let NbtTag::Int(state, index) = entry {
+                // Without gaps every index is below the number of entries, so bigger ones can be rejected before
+                // allocating for them
+                if index < 0 || index as usize >= entry_count {
+                    return Err(McaParseError::InvalidSchematic(format!("palette index {} of {} out of range for {} entries", index, state, entry_count)));
+                }
+                if palette.len() <= index as usize {
+                    palette.resize(index as usize + 1, None);
+                }
+                palette[index as usize] = Some(state.parse::<Block>()?);
+            }
+        }
+        let palette = palette.into_iter().collect::<Option<Vec<Block>>>()
+            .ok_or_else(|| McaParseError::InvalidSchematic("palette has gaps".to_string()))?;
+
+        let block_data = blocks.get_byte_array(data_name)?.iter().map(|b| *b as u8).collect::<Vec<u8>>();
+        let blocks = Self::decode_varints(&block_data)?;
+        if blocks.len() != width as usize * height as usize * length as usize {
+            return Err(McaParseError::InvalidSchematic(format!("expected {}x{}x{} blocks, got {}", width, height, length, blocks.len())));
+        }
+        if blocks.iter().any(|index| *index as usize >= palette.len()) {
+            return Err(McaParseError::InvalidSchematic("block data references missing palette entry".to_string()));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            length,
+            palette,
+            blocks,
+        })
+    }
+
+    /// Decodes the block data, which is a sequence of unsigned LEB128 varints.
+    fn decode_varints(data: &[u8]) -> Result<Vec<u32>> {
+        let mut values = vec![];
+        let mut value = 0u32;
+        let mut shift = 0;
+        for byte in data {
+            if shift >= 32 {
+                return Err(McaParseError::InvalidSchematic("varint too long".to_string()));
+            }
+            value |= ((byte & 0x7F) as u32) << shift;
+            if byte & 0x80 == 0 {
+                values.push(value);
+                value = 0;
+                shift = 0;
+            } else {
+                shift += 7;
+            }
+        }
+        if shift != 0 {
+            return Err(McaParseError::EndOfData);
+        }
+        Ok(values)
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    pub fn palette(&self) -> &Vec<Block> {
+        &self.palette
+    }
+
+    /// Gets block relative to the schematic origin
+    pub fn get(&self, pos: Position) -> Option<&Block> {
+        if pos.x < 0 || pos.y < 0 || pos.z < 0 || pos.x >= self.width as i32 || pos.y >= self.height as i32 || pos.z >= self.length as i32 {
+            return None;
+        }
+        let index = (pos.y as usize*self.length as usize + pos.z as usize)*self.width as usize + pos.x as usize;
+        Some(&self.palette[self.blocks[index] as usize])
+    }
+
+    /// Iterates over every block with its position relative to the schematic origin.
+    pub fn iter_blocks(&self) -> impl Iterator<Item = (Position, &Block)> {
+        let width = self.width as usize;
+        let length = self.length as usize;
+        self.blocks.iter().enumerate().map(move |(i, index)| {
+            let pos = Position::new((i%width) as i32, (i/(width*length)) as i32, ((i/width)%length) as i32);
+            (pos, &self.palette[*index as usize])
+        })
+    }
+}
@@ -1,36 +1,104 @@
 use std::collections::BTreeMap;
 use inbt::NbtTag;
-use log::{debug, error};
+use log::warn;
 use mc_datatypes::VarInt;
-use crate::{Block, Position, McaParseError};
+use crate::{Block, Position, McaParseError, Result};
 
 #[derive(Debug, Clone)]
 pub struct Section {
+    /// Vertical index of the section, e.g. -4 for the lowest section in 1.18+
+    y: i8,
     // 4096 blocks
     blocks: Vec<u16>, // Can hold numbers up to 64k, meanwhile each section can hold a max of 4k blocks
     palette: Vec<Block>,
+    /// `BlockLight` and `SkyLight` as 2048 bytes of packed nibbles, if the section has them
+    block_light: Option<Vec<u8>>,
+    sky_light: Option<Vec<u8>>,
+    /// Biomes of the 4x4x4 cells, as palette indexes in the same order as the blocks. The indexes are left empty
+    /// when there's only one biome (or none stored), so uniform sections don't allocate them.
+    biome_palette: Vec<String>,
+    biomes: Vec<u8>,
 }
 
 pub trait BlockIDGetter {
     fn id_of(&self, block: &Block) -> i32;
+
+    /// Whether the block is left out of the non-empty block count sent to clients. Defaults to the air variants.
+    fn is_empty_block(&self, block: &Block) -> bool {
+        block.is_air()
+    }
 }
 
 impl Section {
-    fn bits_needed_for_palette(palette_size: usize) -> usize {
-        if palette_size == 1 {
+    /// Bits needed to index a palette of the given size, at least `min_bits` unless a single entry needs none.
+    fn bits_needed_for_palette(palette_size: usize, min_bits: usize) -> usize {
+        if palette_size <= 1 {
             return 0;
         }
         let mut palette_bits = palette_size.checked_ilog2().unwrap_or(0) as usize;
         while usize::pow(2, palette_bits as u32) < palette_size {
             palette_bits += 1;
         }
-        if palette_bits < 4 {
-            palette_bits = 4;
+        palette_bits.max(min_bits)
+    }
+
+    /// Packs values of `bits` size into longs, without letting any value span two longs.
+    fn pack_longs(values: impl Iterator<Item = u64>, bits: usize) -> Vec<u64> {
+        let entries_per_long = 64/bits;
+        let mut longs = vec![];
+        for (i, value) in values.enumerate() {
+            if i%entries_per_long == 0 {
+                longs.push(0);
+            }
+            *longs.last_mut().unwrap() |= value<<((i%entries_per_long)*bits);
+        }
+        longs
+    }
+
+    /// Packs values of `bits` size into longs back to back, letting values span two longs like before 20w17a.
+    fn pack_longs_spanning(values: impl Iterator<Item = u64>, bits: usize) -> Vec<u64> {
+        let mut longs = vec![];
+        for (i, value) in values.enumerate() {
+            let (index, shift) = (i*bits/64, i*bits%64);
+            longs.resize(longs.len().max((i*bits + bits).div_ceil(64)), 0);
+            longs[index] |= value<<shift;
+            if shift + bits > 64 {
+                longs[index + 1] |= value>>(64 - shift);
+            }
         }
-        if palette_bits > 8 {
-            palette_bits = 15;
+        longs
+    }
+
+    /// Writes a paletted container of global ids as sent to clients.
+    ///
+    /// Palettes bigger than `max_indirect_bits` can index are left out, and the ids are written directly using
+    /// `direct_bits` instead.
+    fn write_paletted_container(network_data: &mut Vec<u8>, ids: &[i32], min_bits: usize, max_indirect_bits: usize, direct_bits: usize) {
+        let mut palette: Vec<i32> = vec![];
+        for id in ids {
+            if !palette.contains(id) {
+                palette.push(*id);
+            }
         }
-        palette_bits
+        let bits_per_entry = Self::bits_needed_for_palette(palette.len(), min_bits);
+
+        let longs = if bits_per_entry == 0 {
+            network_data.push(0);
+            network_data.append(&mut VarInt::new(palette[0]).bytes);
+            vec![]
+        } else if bits_per_entry <= max_indirect_bits {
+            network_data.push(bits_per_entry as u8);
+            network_data.append(&mut VarInt::new(palette.len() as i32).bytes);
+            network_data.append(&mut palette.iter().flat_map(|id| VarInt::new(*id).bytes).collect::<Vec<u8>>());
+            let indexes = ids.iter().map(|id| palette.iter().position(|p| p == id).unwrap() as u64);
+            Self::pack_longs(indexes, bits_per_entry)
+        } else {
+            network_data.push(direct_bits as u8);
+            Self::pack_longs(ids.iter().map(|id| *id as u64), direct_bits)
+        };
+        // The length is sent even when there's no data
+        network_data.append(&mut VarInt::new(longs.len() as i32).bytes);
+        network_data.append(&mut longs.iter().flat_map(|l| l.to_be_bytes().to_vec()).collect());
     }
 
     fn palette_mask(palette_bits: usize) -> u64 {
@@ -46,136 +114,549 @@ impl Section {
         palette_mask
     }
 
-    pub fn parse_section(tag: NbtTag) -> Result<Section, McaParseError> {
+    /// Creates a section filled with air.
+    pub fn empty(y: i8) -> Self {
+        Self {
+            y,
+            blocks: vec![0; 4096],
+            palette: vec![Block::default()],
+            block_light: None,
+            sky_light: None,
+            biome_palette: vec![],
+            biomes: vec![],
+        }
+    }
+
+    /// Creates a section from a palette and the 4096 palette indexes of its blocks, in y, z, x order.
+    pub fn from_raw(palette: Vec<Block>, indices: Vec<u16>, y: i8) -> Result<Section> {
+        if indices.len() != 4096 {
+            return Err(McaParseError::InvalidSection(format!("expected 4096 indexes, got {}", indices.len())));
+        }
+        if let Some(index) = indices.iter().find(|index| **index as usize >= palette.len()) {
+            return Err(McaParseError::InvalidSection(format!("index {} out of range for palette of size {}", index, palette.len())));
+        }
+        Ok(Section {
+            y,
+            blocks: indices,
+            palette,
+            block_light: None,
+            sky_light: None,
+            biome_palette: vec![],
+            biomes: vec![],
+        })
+    }
+
+    pub fn parse_section(tag: NbtTag) -> Result<Section> {
+        Self::parse_section_into(tag, &mut Vec::with_capacity(4096))
+    }
+
+    /// Same as [`Section::parse_section`], but keeps the table remapping the stored palette to the used entries in
+    /// `scratch`, so callers parsing many sections can reuse a single buffer for it. The blocks themselves belong
+    /// to the section and are always allocated.
+    pub fn parse_section_into(tag: NbtTag, scratch: &mut Vec<u16>) -> Result<Section> {
+        let y = tag.get_byte("Y")?;
         let block_states = tag.get("block_states")?;
-        let palette = block_states.get_list("palette")?;
-        if palette.len() == 1 {
+        let palette_nbt = block_states.get_list("palette")?;
+        // Chunks that are still generating can have a bigger palette without any data, which means all indexes are 0
+        let block_data = block_states.get_long_array("data").unwrap_or_default();
+        Self::from_packed(y, palette_nbt, block_data, false, scratch)
+    }
+
+    /// Parses a section from any version, picking the format from the tags that are there, since chunks in a world
+    /// that was only partially upgraded can be stored in different formats.
+    ///
+    /// - `block_states` is the format since 1.18
+    /// - `Palette` and `BlockStates` is the format from 1.13 to 1.17, where palette indexes can span two longs before
+    ///   1.16 (20w17a)
+    /// - `Blocks` and `Data` are the numeric ids from before 1.13, which need the `legacy` feature
+    ///
+    /// Returns `None` for sections without any blocks, like the ones only holding light.
+    pub fn parse_any(tag: NbtTag, scratch: &mut Vec<u16>, data_version: i32) -> Result<Option<Section>> {
+        let light = |name| tag.get_byte_array(name).ok().map(|light| light.iter().map(|b| *b as u8).collect::<Vec<u8>>());
+        let (block_light, sky_light) = (light("BlockLight"), light("SkyLight"));
+        let (biome_palette, biomes) = Self::parse_biomes(&tag)?;
+        let section = if tag.get("block_states").is_ok() {
+            Self::parse_section_into(tag, scratch)?
+        } else if tag.get("Palette").is_ok() {
+            let y = tag.get_byte("Y")?;
+            let palette_nbt = tag.get_list("Palette")?;
+            let block_data = tag.get_long_array("BlockStates").unwrap_or_default();
+            let spanning = data_version < Self::NON_SPANNING_DATA_VERSION;
+            Self::from_packed(y, palette_nbt, block_data, spanning, scratch)?
+        } else if tag.get("Blocks").is_ok() {
+            Self::parse_legacy(tag, data_version)?
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(Section {
+            block_light,
+            sky_light,
+            biome_palette,
+            biomes,
+            ..section
+        }))
+    }
+
+    /// Parses the `biomes` of a section since 1.18, giving empty palette and indexes if they aren't there.
+    fn parse_biomes(tag: &NbtTag) -> Result<(Vec<String>, Vec<u8>)> {
+        let biomes = match tag.get("biomes") {
+            Ok(biomes) => biomes,
+            Err(_) => return Ok((vec![], vec![])),
+        };
+        let palette = biomes.get_list("palette")?.into_iter().map(|biome| match biome {
+            NbtTag::String(_, name) => Ok(name),
+            _ => Err(McaParseError::InvalidSection("biome palette entry is not a string".to_string())),
+        }).collect::<Result<Vec<String>>>()?;
+        // Like blocks, a single biome is stored without any data
+        let data = biomes.get_long_array("data").unwrap_or_default();
+        if palette.len() <= 1 || data.is_empty() {
+            return Ok((palette, vec![]));
+        }
+
+        let bits = Self::bits_needed_for_palette(palette.len(), 1);
+        let mask = Self::palette_mask(bits);
+        let entries_per_long = 64/bits;
+        let indexes = (0..64).map(|index| {
+            let long = data.get(index/entries_per_long).copied().unwrap_or(0) as u64;
+            ((long>>((index%entries_per_long)*bits)) & mask) as u8
+        }).collect();
+        Ok((palette, indexes))
+    }
+
+    /// First data version where palette indexes don't span two longs (20w17a)
+    pub(crate) const NON_SPANNING_DATA_VERSION: i32 = 2529;
+
+    /// Parses a section from before the 1.13 flattening, with numeric block ids and data values.
+    #[cfg(feature = "legacy")]
+    fn parse_legacy(tag: NbtTag, _data_version: i32) -> Result<Section> {
+        let y = tag.get_byte("Y")?;
+        let ids = tag.get_byte_array("Blocks")?;
+        let data = tag.get_byte_array("Data").unwrap_or_default();
+        let add = tag.get_byte_array("Add").unwrap_or_default();
+        // Data and Add are nibble arrays, with the even indexes in the low bits
+        let nibble = |array: &[i8], index: usize| array.get(index/2).map_or(0, |b| (*b as u8 >> ((index%2)*4)) & 15);
+
+        let mut palette = vec![];
+        let mut blocks = Vec::with_capacity(4096);
+        for index in 0..4096 {
+            let id = ids.get(index).map_or(0, |id| *id as u8 as u16) | (nibble(&add, index) as u16) << 8;
+            let block = match super::legacy::legacy_block(id, nibble(&data, index)) {
+                Some(block) => block,
+                None => {
+                    warn!("Unknown legacy block {}:{}, using air", id, nibble(&data, index));
+                    Block::default()
+                }
+            };
+            blocks.push(Self::index_in_palette(&mut palette, block));
+        }
+        Ok(Section {
+            y,
+            blocks,
+            palette,
+            block_light: None,
+            sky_light: None,
+            biome_palette: vec![],
+            biomes: vec![],
+        })
+    }
+
+    #[cfg(not(feature = "legacy"))]
+    fn parse_legacy(_tag: NbtTag, data_version: i32) -> Result<Section> {
+        Err(McaParseError::UnsupportedVersion(data_version))
+    }
+
+    /// Builds a section from a palette and the palette indexes packed into longs.
+    fn from_packed(y: i8, palette_nbt: Vec<NbtTag>, block_data: Vec<i64>, spanning: bool, scratch: &mut Vec<u16>) -> Result<Section> {
+        if palette_nbt.len() <= 1 || block_data.is_empty() {
             return Ok(Section {
+                y,
                 blocks: vec![0; 4096],
-                palette: vec![Block::new(&palette[0])?],
+                palette: vec![palette_nbt.first().map(Block::new).transpose()?.unwrap_or_default()],
+                block_light: None,
+                sky_light: None,
+                biome_palette: vec![],
+                biomes: vec![],
             });
         }
-        let block_data = block_states.get_long_array("data")?;
+
+        if palette_nbt.len() == 2 {
+            if let Some(section) = Self::from_two_entry_palette(y, &palette_nbt, &block_data)? {
+                return Ok(section);
+            }
+        }
 
         // Bits needed to store the index into palette list, minimum 4 bits.
-        let palette_bits = Self::bits_needed_for_palette(palette.len());
+        let palette_bits = Self::bits_needed_for_palette(palette_nbt.len(), 4);
         // Calculate the palette mask
         let palette_mask = Self::palette_mask(palette_bits);
         let palette_entries_per_long = 64/palette_bits;
 
-        let mut blocks = vec![Block::default(); 4096];
-        for y in 0..16 {
-            for z in 0..16 {
-                for x in 0..16 {
-                    let block_pos = y*16*16 + z*16 + x;
-                    let block_data_index = block_pos/palette_entries_per_long;
-                    let block_data_sub_index = block_pos%palette_entries_per_long;
-                    let mask_shift = palette_bits*block_data_sub_index;
-                    let palette_index = (block_data[block_data_index] as u64 & (palette_mask<<mask_shift))>>mask_shift;
-                    if palette_index as usize >= palette.len() {
-                        // Will panic after this, just debug info for now
-                        error!("palette_bits: {}", palette_bits);
-                        error!("palette_mask: {:0b}", palette_mask);
-                        error!("block_data: {:064b}", block_data[block_data_index]);
-                        error!("block_data_index: {}", block_data_sub_index);
-                        error!("palette_index: {palette_index}");
-                    }
-                    let block = &palette[palette_index as usize];
-                    blocks[block_pos] = Block::new(block)?;
-                    //blocks.push(Block::new(block_name));
+        // Sections blended from upgraded worlds can have less data than the palette size implies
+        let long_at = |index: usize| block_data.get(index).copied().unwrap_or(0) as u64;
+        let mut blocks = Vec::with_capacity(4096);
+        for block_pos in 0..4096 {
+            if spanning {
+                let bit = block_pos*palette_bits;
+                let (index, shift) = (bit/64, bit%64);
+                let mut value = long_at(index)>>shift;
+                if shift + palette_bits > 64 {
+                    value |= long_at(index + 1)<<(64 - shift);
                 }
+                blocks.push((value & palette_mask) as u16);
+                continue;
             }
+            let block_data_index = block_pos/palette_entries_per_long;
+            let block_data_sub_index = block_pos%palette_entries_per_long;
+            let mask_shift = palette_bits*block_data_sub_index;
+            blocks.push(((long_at(block_data_index) & (palette_mask<<mask_shift))>>mask_shift) as u16);
         }
 
+        // Only keep the palette entries that are used, and only decode each of them once. The scratch buffer maps
+        // the stored palette indexes to the kept entries, with u16::MAX for the ones not used so far.
         let mut palette = vec![];
-        let mut palette_indexes = vec![];
-        for block in blocks {
-            if !palette.contains(&block) {
-                palette.push(block.clone());
-            }
-            let index = palette.iter().enumerate().find(|b| b.1.eq(&block)).unwrap().0;
-            palette_indexes.push(index as u16);
+        scratch.clear();
+        scratch.resize(palette_nbt.len(), u16::MAX);
+        for index in blocks.iter_mut() {
+            let palette_index = *index as usize;
+            *index = match (palette_nbt.get(palette_index), scratch.get(palette_index).copied()) {
+                (Some(_), Some(remapped)) if remapped != u16::MAX => remapped,
+                (Some(block), _) => {
+                    let remapped = Self::index_in_palette(&mut palette, Block::new(block)?);
+                    scratch[palette_index] = remapped;
+                    remapped
+                }
+                (None, _) => {
+                    warn!("Palette index {} out of range for palette of size {}, using air", palette_index, palette_nbt.len());
+                    Self::index_in_palette(&mut palette, Block::default())
+                }
+            };
         }
 
         Ok(Section {
-            blocks: palette_indexes,
+            y,
+            blocks,
             palette,
+            block_light: None,
+            sky_light: None,
+            biome_palette: vec![],
+            biomes: vec![],
         })
     }
 
-    /// Takes a function to map identifiers to numbers, e.g. minecraft:air -> 0
-    pub fn network_data(&self, id_getter: &Box<dyn BlockIDGetter>) -> Vec<u8> {
-        let mut network_data = vec![];
-        let mut palette: Vec<Block> = vec![];
-        let mut block_count = 0;
-        for block_indexes in &self.blocks {
-            let block = &self.palette[*block_indexes as usize];
-            if !palette.contains(block) {
-                palette.push(block.clone());
+    /// Fast path for the very common two-entry palettes (e.g. stone and air), which always use 4 bits per entry
+    /// and never span two longs.
+    ///
+    /// Returns `None` if an index is out of range, leaving it to the generic path to deal with.
+    fn from_two_entry_palette(y: i8, palette_nbt: &[NbtTag], block_data: &[i64]) -> Result<Option<Section>> {
+        let mut blocks = Vec::with_capacity(4096);
+        let mut used = [false; 2];
+        for block_pos in 0..4096 {
+            let long = block_data.get(block_pos/16).copied().unwrap_or(0) as u64;
+            let index = ((long>>((block_pos%16)*4)) & 0b1111) as u16;
+            if index > 1 {
+                return Ok(None);
             }
-            block_count += (!block.identifier.eq("minecraft:air")) as u16;
+            used[index as usize] = true;
+            blocks.push(index);
         }
 
-        let mut bits_per_entry = Self::bits_needed_for_palette(palette.len());
+        let first = Block::new(&palette_nbt[0])?;
+        let second = Block::new(&palette_nbt[1])?;
+        // Keep the palette down to the used entries like the generic path does
+        let palette = match used {
+            [true, true] if !first.eq(&second) => vec![first, second],
+            [false, true] => {
+                blocks.iter_mut().for_each(|index| *index = 0);
+                vec![second]
+            }
+            _ => {
+                blocks.iter_mut().for_each(|index| *index = 0);
+                vec![first]
+            }
+        };
+        Ok(Some(Section {
+            y,
+            blocks,
+            palette,
+            block_light: None,
+            sky_light: None,
+            biome_palette: vec![],
+            biomes: vec![],
+        }))
+    }
+
+    /// Finds the block in the palette, adding it if it isn't there yet.
+    fn index_in_palette(palette: &mut Vec<Block>, block: Block) -> u16 {
+        match palette.iter().position(|b| b.eq(&block)) {
+            Some(index) => index as u16,
+            None => {
+                palette.push(block);
+                (palette.len() - 1) as u16
+            }
+        }
+    }
+
+    /// Takes a function to map identifiers to numbers, e.g. minecraft:air -> 0
+    pub fn network_data(&self, id_getter: &Box<dyn BlockIDGetter>) -> Vec<u8> {
+        let mut network_data = vec![];
+        let palette_ids = self.palette.iter().map(|block| id_getter.id_of(block)).collect::<Vec<i32>>();
+        let block_count = self.blocks.iter()
+            .filter(|index| !id_getter.is_empty_block(&self.palette[**index as usize]))
+            .count() as u16;
 
         // Block count as short
         network_data.append(&mut block_count.to_be_bytes().to_vec());
 
-        network_data.push(bits_per_entry as u8);
+        let block_ids = self.blocks.iter().map(|index| palette_ids[*index as usize]).collect::<Vec<i32>>();
+        Self::write_paletted_container(&mut network_data, &block_ids, 4, 8, 15);
 
-        if bits_per_entry == 0 {
-            network_data.append(&mut VarInt::new(id_getter.id_of(&palette[0])).bytes);
-            network_data.push(0);
-        } else if (4..9).contains(&bits_per_entry) {
-            network_data.append(&mut VarInt::new(palette.len() as i32).bytes);
-            network_data.append(&mut palette.iter().flat_map(|s| VarInt::new(id_getter.id_of(s)).bytes).collect::<Vec<u8>>());
-
-            let entries_per_long = 64/bits_per_entry;
-
-            let mut longs = vec![];
-            for blocks in self.blocks.chunks(entries_per_long) {
-                let mut long = 0;
-                for (i, block_index) in blocks.iter().enumerate() {
-                    let block = &self.palette[*block_index as usize];
-                    let mut palette_index = None;
-                    for (i, palette_entry) in palette.iter().enumerate() {
-                        if palette_entry.eq(block) {
-                            palette_index = Some(i);
-                            break;
-                        }
-                    }
-                    long |= palette_index.unwrap()<<(i*bits_per_entry)
-                }
-                longs.push(long);
-            }
-            network_data.append(&mut VarInt::new(longs.len() as i32).bytes);
-            network_data.append(&mut longs.iter().flat_map(|l| l.to_be_bytes().to_vec()).collect());
+        // Fake biome info, a single biome (nr. 8) for the whole section
+        Self::write_paletted_container(&mut network_data, &[8; 64], 1, 3, 6);
+        network_data
+    }
+
+    /// Packs the blocks the way they're stored in region files, returning the long array, the bits per entry and
+    /// the palette the indexes point into.
+    ///
+    /// The section is unpacked when parsed, so this packs it again and the palette only has the used blocks. A
+    /// single-entry palette has 0 bits per entry and no data.
+    pub fn packed_block_data(&self) -> (Vec<i64>, usize, Vec<Block>) {
+        let bits_per_entry = Self::bits_needed_for_palette(self.palette.len(), 4);
+        let data = if bits_per_entry == 0 {
+            vec![]
         } else {
-            let entries_per_long = 64/bits_per_entry;
-
-            let mut longs = vec![];
-            for blocks in self.blocks.chunks(entries_per_long) {
-                let mut long = 0;
-                for (i, block_index) in blocks.iter().enumerate() {
-                    let block = &self.palette[*block_index as usize];
-                    let block_id = id_getter.id_of(block) as u64;
-                    long |= block_id<<(i*bits_per_entry)
+            Self::pack_longs(self.blocks.iter().map(|index| *index as u64), bits_per_entry)
+                .into_iter()
+                .map(|long| long as i64)
+                .collect()
+        };
+        (data, bits_per_entry, self.palette.clone())
+    }
+
+    /// Removes the palette entries that aren't used by any block, e.g. after [`Section::replace_all`] merged two
+    /// entries, and remaps the indexes to the remaining entries.
+    pub fn prune_palette(&mut self) {
+        let mut used = vec![false; self.palette.len()];
+        for index in &self.blocks {
+            used[*index as usize] = true;
+        }
+        if used.iter().all(|used| *used) {
+            return;
+        }
+        let mut remapped = vec![0; self.palette.len()];
+        let mut palette = vec![];
+        for (index, block) in self.palette.drain(..).enumerate() {
+            if used[index] {
+                remapped[index] = palette.len() as u16;
+                palette.push(block);
+            }
+        }
+        for index in self.blocks.iter_mut() {
+            *index = remapped[*index as usize];
+        }
+        self.palette = palette;
+    }
+
+    /// Returns the section in the format it's stored in chunks, with `Y`, `block_states` and the biomes and light
+    /// if the section has them. Unused palette entries are left out.
+    pub fn to_nbt(&self) -> NbtTag {
+        let mut pruned = self.clone();
+        pruned.prune_palette();
+        let (data, _, palette) = pruned.packed_block_data();
+        let mut block_states = vec![NbtTag::List("palette".to_string(), palette.iter().map(|b| b.to_nbt()).collect())];
+        if !data.is_empty() {
+            block_states.push(NbtTag::LongArray("data".to_string(), data));
+        }
+        let mut tags = vec![
+            NbtTag::Byte("Y".to_string(), self.y),
+            NbtTag::Compound("block_states".to_string(), block_states),
+        ];
+        if !self.biome_palette.is_empty() {
+            let mut biomes = vec![NbtTag::List("palette".to_string(), self.biome_palette.iter().map(|biome| NbtTag::String(String::new(), biome.clone())).collect())];
+            if !self.biomes.is_empty() {
+                let bits = Self::bits_needed_for_palette(self.biome_palette.len(), 1);
+                let data = Self::pack_longs(self.biomes.iter().map(|index| *index as u64), bits);
+                biomes.push(NbtTag::LongArray("data".to_string(), data.into_iter().map(|long| long as i64).collect()));
+            }
+            tags.push(NbtTag::Compound("biomes".to_string(), biomes));
+        }
+        tags.extend(self.light_nbt());
+        NbtTag::Compound(String::new(), tags)
+    }
+
+    /// The `BlockLight` and `SkyLight` tags of the light the section has
+    fn light_nbt(&self) -> Vec<NbtTag> {
+        [("BlockLight", &self.block_light), ("SkyLight", &self.sky_light)].into_iter()
+            .filter_map(|(name, light)| Some(NbtTag::ByteArray(name.to_string(), light.as_ref()?.iter().map(|b| *b as i8).collect())))
+            .collect()
+    }
+
+    /// Same as [`Section::to_nbt`], but in the format from before 1.18 (21w43a) with `Palette` and `BlockStates`,
+    /// where the indexes span two longs if `spanning` is set like before 20w17a.
+    ///
+    /// Those versions ignore sections without `BlockStates`, so it's written even for a single-entry palette. Biomes
+    /// were stored for the whole chunk back then, so only the light is written with it.
+    pub fn to_level_nbt(&self, spanning: bool) -> NbtTag {
+        let mut pruned = self.clone();
+        pruned.prune_palette();
+        let bits = Self::bits_needed_for_palette(pruned.palette.len().max(2), 4);
+        let indexes = pruned.blocks.iter().map(|index| *index as u64);
+        let data = if spanning { Self::pack_longs_spanning(indexes, bits) } else { Self::pack_longs(indexes, bits) };
+        let mut tags = vec![
+            NbtTag::Byte("Y".to_string(), self.y),
+            NbtTag::List("Palette".to_string(), pruned.palette.iter().map(|b| b.to_nbt()).collect()),
+            NbtTag::LongArray("BlockStates".to_string(), data.into_iter().map(|long| long as i64).collect()),
+        ];
+        tags.extend(self.light_nbt());
+        NbtTag::Compound(String::new(), tags)
+    }
+
+    /// Whether every block in the section is one of the air variants
+    pub fn is_all_air(&self) -> bool {
+        self.palette.iter().all(|block| block.is_air())
+    }
+
+    pub fn y(&self) -> i8 {
+        self.y
+    }
+
+    pub fn palette(&self) -> &Vec<Block> {
+        &self.palette
+    }
+
+    /// Sets block relative to section origin, adding it to the palette if needed.
+    pub fn set(&mut self, pos: Position, block: Block) {
+        let index = Self::index_in_palette(&mut self.palette, block);
+        self.blocks[pos.block_index_in_section()] = index;
+    }
+
+    /// Sets every block in the section to `block`.
+    pub fn fill(&mut self, block: Block) {
+        self.blocks.clear();
+        self.blocks.resize(4096, 0);
+        self.palette = vec![block];
+    }
+
+    /// Replaces every `from` block with `to`, returning how many blocks were changed.
+    ///
+    /// This only touches the palette, unless `to` is already in it, then the indexes are merged into the existing
+    /// entry and the entry for `from` is left unused.
+    pub fn replace_all(&mut self, from: &Block, to: Block) -> usize {
+        if from.eq(&to) {
+            return 0;
+        }
+        let from_index = match self.palette.iter().position(|b| b.eq(from)) {
+            Some(index) => index as u16,
+            None => return 0,
+        };
+        let count = self.blocks.iter().filter(|index| **index == from_index).count();
+        match self.palette.iter().position(|b| b.eq(&to)) {
+            Some(to_index) => {
+                for index in self.blocks.iter_mut().filter(|index| **index == from_index) {
+                    *index = to_index as u16;
                 }
-                longs.push(long);
             }
-            network_data.append(&mut VarInt::new(longs.len() as i32).bytes);
-            network_data.append(&mut longs.iter().flat_map(|l| l.to_be_bytes().to_vec()).collect());
+            None => self.palette[from_index as usize] = to,
         }
+        count
+    }
 
-        // Fake biome info
-        network_data.push(0); // Only a single biome so no bits per entry
-        network_data.append(&mut VarInt::new(8).bytes); // Which biome? biome nr. 8
-        network_data.push(0); // Data array is not included, but we still need to have the length
-        network_data
+    /// Iterates over all 4096 blocks in y, z, x order, with positions relative to the section origin.
+    pub fn iter_blocks(&self) -> impl Iterator<Item = (Position, &Block)> {
+        self.blocks.iter().enumerate().map(move |(i, index)| (Self::position_of_index(i), &self.palette[*index as usize]))
+    }
+
+    /// Same as [`Section::iter_blocks`], but consumes the section to yield owned blocks, e.g. to send them to other
+    /// threads. Every block is cloned out of the palette, so prefer the borrowing version otherwise.
+    pub fn into_block_iter(self) -> impl Iterator<Item = (Position, Block)> {
+        let Section { blocks, palette, .. } = self;
+        blocks.into_iter().enumerate().map(move |(i, index)| (Self::position_of_index(i), palette[index as usize].clone()))
+    }
+
+    /// Same as [`Section::into_block_iter`], but clones the blocks out of a borrowed section.
+    pub fn iter_owned(&self) -> impl Iterator<Item = (Position, Block)> + '_ {
+        self.iter_blocks().map(|(pos, block)| (pos, block.clone()))
+    }
+
+    /// Same as [`Section::iter_blocks`], but skips air. Air is checked once per palette entry instead of for every
+    /// block, and sections that are only air aren't iterated at all.
+    pub fn iter_non_air(&self) -> impl Iterator<Item = (Position, &Block)> {
+        let air = self.palette.iter().map(|block| block.is_air()).collect::<Vec<bool>>();
+        let block_count = if air.iter().all(|is_air| *is_air) { 0 } else { self.blocks.len() };
+        self.blocks.iter().enumerate()
+            .take(block_count)
+            .filter(move |(_, index)| !air[**index as usize])
+            .map(move |(i, index)| (Self::position_of_index(i), &self.palette[*index as usize]))
+    }
+
+    /// Iterates over all 4096 blocks along a Z-order (Morton) curve, with positions relative to the section origin.
+    ///
+    /// Blocks that are close together in the iteration are also close together in the section, which is a lot
+    /// friendlier to caches than y, z, x order for passes that look at the neighbors of each block.
+    pub fn iter_morton(&self) -> impl Iterator<Item = (Position, &Block)> {
+        (0..4096).map(move |code| {
+            let pos = Self::morton_decode(code);
+            (pos, &self.palette[self.blocks[pos.block_index_in_section()] as usize])
+        })
+    }
+
+    /// Splits a 12 bit Morton code into its x, y and z coordinates, with x in the lowest bit of each group of three
+    fn morton_decode(code: usize) -> Position {
+        let axis = |shift: usize| (0..4).map(|bit| ((code >> (bit*3 + shift)) & 1) << bit).sum::<usize>() as i32;
+        Position::new(axis(0), axis(1), axis(2))
+    }
+
+    /// The inverse of [`Position::block_index_in_section`]
+    fn position_of_index(index: usize) -> Position {
+        Position::new((index%16) as i32, (index/256) as i32, ((index/16)%16) as i32)
+    }
+
+    /// Gets the palette index of the block relative to section origin
+    pub fn index_at(&self, pos: Position) -> u16 {
+        self.blocks[pos.block_index_in_section()]
+    }
+
+    pub fn palette_entry(&self, index: u16) -> Option<&Block> {
+        self.palette.get(index as usize)
+    }
+
+    /// Block light (0-15) relative to section origin, or `None` if the section has no block light stored
+    pub fn block_light(&self, pos: Position) -> Option<u8> {
+        Self::light_at(self.block_light.as_ref()?, pos)
+    }
+
+    /// Sky light (0-15) relative to section origin, or `None` if the section has no sky light stored
+    pub fn sky_light(&self, pos: Position) -> Option<u8> {
+        Self::light_at(self.sky_light.as_ref()?, pos)
+    }
+
+    /// Sets the biome of the whole section
+    #[cfg(feature = "testutil")]
+    pub(crate) fn fill_biome(&mut self, biome: &str) {
+        self.biome_palette = vec![biome.to_string()];
+        self.biomes = vec![];
+    }
+
+    /// Biome at the position relative to section origin, or `None` if the section has no biomes stored
+    pub fn biome(&self, pos: Position) -> Option<&str> {
+        let cell = (pos.y.rem_euclid(16)/4*16 + pos.z.rem_euclid(16)/4*4 + pos.x.rem_euclid(16)/4) as usize;
+        let index = self.biomes.get(cell).copied().unwrap_or(0);
+        self.biome_palette.get(index as usize).map(|biome| biome.as_str())
+    }
+
+    /// Light arrays are nibbles in the same order as the blocks, with the even indexes in the low bits
+    fn light_at(light: &[u8], pos: Position) -> Option<u8> {
+        let index = pos.block_index_in_section();
+        Some((light.get(index/2)? >> ((index%2)*4)) & 15)
     }
 
     /// Gets block relative to section origin
     pub fn get(&self, pos: Position) -> Block {
-        self.palette[self.blocks[pos.block_index_in_section()] as usize].clone()
+        self.get_ref(pos).clone()
+    }
+
+    /// Same as [`Section::get`], but borrows the block from the palette
+    pub fn get_ref(&self, pos: Position) -> &Block {
+        &self.palette[self.blocks[pos.block_index_in_section()] as usize]
     }
 }
\ No newline at end of file
@@ -0,0 +1,62 @@
+//! Generators for synthetic region files, enabled by the `testutil` feature.
+//!
+//! The chunks are built in memory and written with the same encoder used for saving edited chunks, so the
+//! generated regions can be varied without committing binary files.
+
+use crate::{Block, Position};
+use crate::parser::chunk::Chunk;
+use crate::parser::nbt_writer;
+use crate::parser::section::Section;
+
+/// Data version of the generated chunks (1.20.1)
+const DATA_VERSION: i32 = 3465;
+/// Lowest and highest section of the generated chunks, the 1.18+ overworld height
+const MIN_SECTION_Y: i8 = -4;
+const MAX_SECTION_Y: i8 = 19;
+
+/// Makes the bytes of region `r.0.0.mca` with all 1024 chunks generated, each of them `height` layers of `block`
+/// from the bottom of the world (y -64) with air above, and plains as the only biome.
+pub fn make_flat_region(block: Block, height: i32) -> Vec<u8> {
+    let height = height.clamp(0, (MAX_SECTION_Y - MIN_SECTION_Y + 1) as i32*16);
+    let sections = (MIN_SECTION_Y..=MAX_SECTION_Y)
+        .map(|y| flat_section(y, &block, height - (y - MIN_SECTION_Y) as i32*16))
+        .collect::<Vec<Section>>();
+
+    // Chunks are encoded one at a time, so only their compressed payloads are kept around
+    let mut region = vec![0; 8192];
+    for slot in 0..1024 {
+        let mut chunk = Chunk::new_empty(Position::new(slot%32, 0, slot/32), MIN_SECTION_Y, MAX_SECTION_Y, DATA_VERSION);
+        *chunk.sections_mut() = sections.clone();
+        let payload = chunk.to_nbt().and_then(|nbt| nbt_writer::write_chunk_payload(&nbt))
+            .expect("flat chunks are small and in a supported version");
+        let offset = region.len()/4096;
+        region.extend_from_slice(&payload);
+        region.resize(region.len().div_ceil(4096)*4096, 0);
+
+        let sectors = region.len()/4096 - offset;
+        let location = slot as usize*4;
+        region[location..location + 3].copy_from_slice(&(offset as u32).to_be_bytes()[1..]);
+        region[location + 3] = sectors as u8;
+    }
+    region
+}
+
+/// A section with its lowest `layers` layers (clamped to 0..=16) of `block`, and air above.
+fn flat_section(y: i8, block: &Block, layers: i32) -> Section {
+    let mut section = Section::empty(y);
+    section.fill_biome("minecraft:plains");
+    match layers.clamp(0, 16) {
+        0 => {}
+        16 => section.fill(block.clone()),
+        layers => {
+            for y in 0..layers {
+                for z in 0..16 {
+                    for x in 0..16 {
+                        section.set(Position::new(x, y, z), block.clone());
+                    }
+                }
+            }
+        }
+    }
+    section
+}